@@ -30,6 +30,8 @@ fn main() {
         .add_plugins(DefaultPlugins)
         // add out states driver
         .add_loopless_state(GameState::MainMenu)
+        // despawn entities with a `StateScoped` component when exiting their state
+        .enable_state_scoped_entities::<GameState>()
         // Add a FixedTimestep, cuz we can!
         .add_fixed_timestep(
             Duration::from_millis(125),
@@ -37,11 +39,8 @@ fn main() {
             "my_fixed_update",
         )
         // menu setup (state enter) systems
+        // (no cleanup systems needed; our entities are `StateScoped`)
         .add_enter_system(GameState::MainMenu, setup_menu)
-        // menu cleanup (state exit) systems
-        .add_exit_system(GameState::MainMenu, despawn_with::<MainMenu>)
-        // game cleanup (state exit) systems
-        .add_exit_system(GameState::InGame, despawn_with::<MySprite>)
         // menu stuff
         .add_system_set(
             ConditionSet::new()
@@ -81,10 +80,6 @@ fn main() {
 #[derive(Component)]
 struct MySprite;
 
-/// Marker for the main menu entity
-#[derive(Component)]
-struct MainMenu;
-
 /// Marker for the main game camera entity
 #[derive(Component)]
 struct GameCamera;
@@ -123,13 +118,6 @@ fn spacebar_pressed(kbd: Res<Input<KeyCode>>) -> bool {
     kbd.pressed(KeyCode::Space)
 }
 
-/// Despawn all entities with a given component type
-fn despawn_with<T: Component>(mut commands: Commands, q: Query<Entity, With<T>>) {
-    for e in q.iter() {
-        commands.entity(e).despawn_recursive();
-    }
-}
-
 /// Spawn a MySprite entity
 fn spawn_sprite(mut commands: Commands) {
     let mut rng = thread_rng();
@@ -145,7 +133,7 @@ fn spawn_sprite(mut commands: Commands) {
             rng.gen_range(0.0..100.0),
         ),
         ..Default::default()
-    }, MySprite));
+    }, MySprite, StateScoped(GameState::InGame)));
 }
 
 /// Spawn the camera
@@ -233,7 +221,7 @@ fn setup_menu(mut commands: Commands, ass: Res<AssetServer>) {
                 ..Default::default()
             },
             ..Default::default()
-        }, MainMenu))
+        }, StateScoped(GameState::MainMenu)))
         .id();
 
     let butt_enter = commands
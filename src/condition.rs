@@ -417,6 +417,20 @@ pub trait ConditionHelpers: Sized {
         self.run_unless_resource_equals(CurrentState(state))
     }
 
+    #[cfg(feature = "states")]
+    /// Helper: run in any state that matches the given predicate (checks the [`CurrentState`] resource)
+    ///
+    /// Useful for states that carry data, like `matches!(state, GameState::InGame { .. })`.
+    fn run_in_state_matching<T: bevy_ecs::schedule::StateData>(self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static) -> Self {
+        self.run_if(move |res: Option<Res<CurrentState<T>>>| {
+            if let Some(res) = res {
+                predicate(&res.0)
+            } else {
+                false
+            }
+        })
+    }
+
     #[cfg(feature = "bevy-compat")]
     /// Helper: run in a specific Bevy state (checks the `State<T>` resource)
     fn run_in_bevy_state<T: bevy_ecs::schedule::StateData>(self, state: T) -> Self {
@@ -530,6 +544,15 @@ pub trait IntoConditionalSystem<Params>: IntoSystem<(), (), Params> + Sized {
         self.into_conditional().run_not_in_state(state)
     }
 
+    /// (provided so users don't have to type `.into_conditional()` first)
+    #[cfg(feature = "states")]
+    fn run_in_state_matching<T: bevy_ecs::schedule::StateData>(
+        self,
+        predicate: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> ConditionalSystemDescriptor {
+        self.into_conditional().run_in_state_matching(predicate)
+    }
+
     /// (provided so users don't have to type `.into_conditional()` first)
     #[cfg(feature = "bevy-compat")]
     fn run_in_bevy_state<T: bevy_ecs::schedule::StateData>(
@@ -794,6 +817,20 @@ impl ConditionSet {
         self.run_unless_resource_equals(CurrentState(state))
     }
 
+    #[cfg(feature = "states")]
+    /// Helper: run in any state that matches the given predicate (checks the [`CurrentState`] resource)
+    ///
+    /// Useful for states that carry data, like `matches!(state, GameState::InGame { .. })`.
+    pub fn run_in_state_matching<T: bevy_ecs::schedule::StateData>(self, predicate: impl Fn(&T) -> bool + Clone + Send + Sync + 'static) -> Self {
+        self.run_if(move |res: Option<Res<CurrentState<T>>>| {
+            if let Some(res) = res {
+                predicate(&res.0)
+            } else {
+                false
+            }
+        })
+    }
+
     #[cfg(feature = "bevy-compat")]
     /// Helper: run in a specific Bevy state (checks the `State<T>` resource)
     pub fn run_in_bevy_state<T: bevy_ecs::schedule::StateData>(self, state: T) -> Self {
@@ -14,6 +14,8 @@ pub mod condition;
 pub mod fixedtimestep;
 #[cfg(feature = "states")]
 pub mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 /// Prelude: convenient import for all the user-facing APIs provided by the crate
 pub mod prelude {
@@ -27,9 +29,37 @@ pub mod prelude {
     pub use crate::fixedtimestep::app::AppLooplessFixedTimestepExt;
 
     #[cfg(feature = "states")]
-    pub use crate::state::{CurrentState, NextState, StateTransitionStage};
+    pub use crate::state::{CurrentState, NextState, GoBack, SameStatePolicy, StateHistory, StateQueue, StateStageError, StateTransitionLoop, StateTransitionStage};
+    #[cfg(feature = "state-scoped")]
+    pub use crate::state::StateScoped;
     #[cfg(feature = "states")]
     pub use crate::state::schedule::ScheduleLooplessStateExt;
     #[cfg(all(feature = "states", feature = "app"))]
     pub use crate::state::app::AppLooplessStateExt;
+    #[cfg(all(feature = "states", feature = "bevy-compat"))]
+    pub use crate::state::bevy_compat::schedule::ScheduleLooplessBevyStateExt;
+    #[cfg(all(feature = "states", feature = "bevy-compat", feature = "app"))]
+    pub use crate::state::bevy_compat::app::{AppLooplessBevyStateExt, BevyStateBridgePlugin};
+    #[cfg(feature = "states")]
+    pub use crate::state::allowed::StateTransitionRejected;
+    #[cfg(feature = "states")]
+    pub use crate::state::commands::{StateCommands, StateRequestConflict};
+    #[cfg(feature = "states")]
+    pub use crate::state::coverage::{LooplessStates, StateCoverageReport};
+    #[cfg(feature = "states")]
+    pub use crate::state::readiness::PendingTransition;
+    #[cfg(feature = "timed")]
+    pub use crate::state::timed::Transitioning;
+    #[cfg(feature = "states")]
+    pub use crate::state::entity::{EntityState, NextEntityState, TransitioningEntities, EntityStateTransitionStage};
+    #[cfg(feature = "states")]
+    pub use crate::state::entity::schedule::ScheduleLooplessEntityStateExt;
+    #[cfg(all(feature = "states", feature = "app"))]
+    pub use crate::state::entity::app::AppLooplessEntityStateExt;
+    #[cfg(all(feature = "reflect", feature = "app"))]
+    pub use crate::state::reflect::app::AppLooplessStateReflectExt;
+    #[cfg(feature = "serde")]
+    pub use crate::state::snapshot::{StateSnapshot, snapshot_states, restore_states};
+    #[cfg(all(feature = "serde", feature = "app"))]
+    pub use crate::state::snapshot::app::AppLooplessStateSnapshotExt;
 }
@@ -8,23 +8,347 @@
 //! aspects of your application) and combine them trivially.
 //!
 //! (see `examples/menu.rs` for a full example)
+//!
+//! To request state transitions from systems, you can use the [`StateCommands`](commands::StateCommands)
+//! extension methods on `Commands`, as an alternative to inserting [`NextState`] directly.
+//!
+//! If you need a separate state machine for each entity, see the [`entity`] module.
 use bevy_ecs::schedule::{Stage, StateData, StageLabel, IntoSystemDescriptor, SystemSet, SystemStage};
+#[cfg(feature = "state-scoped")]
+use bevy_ecs::component::Component;
+#[cfg(feature = "state-scoped")]
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::Events;
 use bevy_ecs::world::World;
 use bevy_ecs::system::Resource;
-use bevy_utils::HashMap;
+use bevy_utils::{HashMap, HashSet, Instant};
+#[cfg(feature = "timed")]
+use bevy_utils::Duration;
+use bevy_utils::tracing::{error, warn};
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub mod allowed;
+#[cfg(feature = "bevy-compat")]
+pub mod bevy_compat;
+pub mod commands;
+pub mod coverage;
+pub mod entity;
+pub mod graph;
+pub mod readiness;
+#[cfg(feature = "reflect")]
+pub mod reflect;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+#[cfg(feature = "timed")]
+pub mod timed;
 
-use std::any::TypeId;
+type ParentCheck = Box<dyn Fn(&World) -> bool + Send + Sync>;
+type ComputeFn<T> = Box<dyn Fn(&World) -> Option<T> + Send + Sync>;
+type MatchFn<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+type WorldFn = Box<dyn Fn(&mut World) + Send + Sync>;
+
+/// A resource that exists only while in a given state
+struct ScopedResource {
+    insert: WorldFn,
+    remove: WorldFn,
+}
+
+/// A sub-state, set up/torn down when its parent state is entered/exited
+struct SubState<T> {
+    parent: T,
+    set_up: WorldFn,
+    tear_down: WorldFn,
+}
 
 /// This will be available as a resource, indicating the current state
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[derive(Resource)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CurrentState<T>(pub T);
 
 /// When you want to change state, insert this as a resource
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[derive(Resource)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NextState<T>(pub T);
 
+/// Insert this as a resource to request a sequence of state transitions
+///
+/// The [`StateTransitionStage`] will process the queued states in order,
+/// whenever there is no [`NextState`] pending. `NextState` takes priority,
+/// the queue is only checked after it has been handled.
+///
+/// By default, the whole queue is processed in a single run of the stage.
+/// Use [`one_per_frame`](Self::one_per_frame) to only perform one transition
+/// from the queue every time the stage runs. States queued while the stage
+/// is running (such as from enter/exit systems) wait for its next run.
+///
+/// Transitions from the queue do not count towards the
+/// [maximum number of transitions](StateTransitionStage::set_max_transitions),
+/// so the queue can be longer than that.
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct StateQueue<T> {
+    queue: VecDeque<T>,
+    one_per_frame: bool,
+    duplicates: QueueDuplicates,
+}
+
+/// What should [`StateQueue`] do when a state is pushed that is already queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueDuplicates {
+    /// Queue every state that is pushed
+    #[default]
+    Allow,
+    /// Ignore the state if it is the same as the last queued state
+    SkipConsecutive,
+    /// Ignore the state if it is already anywhere in the queue
+    SkipQueued,
+}
+
+impl<T: StateData> StateQueue<T> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            one_per_frame: false,
+            duplicates: QueueDuplicates::Allow,
+        }
+    }
+
+    /// Builder method to only perform one transition from the queue per frame
+    pub fn one_per_frame(mut self) -> Self {
+        self.one_per_frame = true;
+        self
+    }
+
+    /// Builder method to set the policy for duplicate states
+    pub fn with_duplicates(mut self, duplicates: QueueDuplicates) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// Builder method to add a state at the end of the queue
+    pub fn with_state(mut self, state: T) -> Self {
+        self.push(state);
+        self
+    }
+
+    /// Add a state at the end of the queue
+    ///
+    /// Returns `false` if the state was not queued, due to the duplicates policy.
+    pub fn push(&mut self, state: T) -> bool {
+        let skip = match self.duplicates {
+            QueueDuplicates::Allow => false,
+            QueueDuplicates::SkipConsecutive => self.queue.back() == Some(&state),
+            QueueDuplicates::SkipQueued => self.queue.contains(&state),
+        };
+        if !skip {
+            self.queue.push_back(state);
+        }
+        !skip
+    }
+
+    /// Remove all queued states
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    /// The number of queued states
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Is the queue empty?
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Iterate over the queued states, in order
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.queue.iter()
+    }
+}
+
+impl<T: StateData> Default for StateQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Add this component to entities that should only exist in a given state
+///
+/// When the state is exited, the entity will be despawned (recursively,
+/// along with all its children). This has to be enabled for the state type,
+/// using [`StateTransitionStage::enable_state_scoped_entities`].
+#[cfg(feature = "state-scoped")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Component)]
+pub struct StateScoped<T: StateData>(pub T);
+
+/// Resource with a record of the previous values of a state
+///
+/// Only available if enabled on the [`StateTransitionStage`] (see
+/// [`enable_history`](StateTransitionStage::enable_history)).
+/// A new entry is added every time the state is exited, except when
+/// going back using [`GoBack`], or when re-entering the same state. The
+/// oldest entries are discarded when the capacity is exceeded.
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct StateHistory<T> {
+    entries: VecDeque<StateHistoryEntry<T>>,
+    capacity: usize,
+}
+
+/// An entry in the [`StateHistory`]
+#[derive(Debug, Clone)]
+pub struct StateHistoryEntry<T> {
+    /// The previous state value
+    pub state: T,
+    /// When the state was exited
+    pub exited_at: Instant,
+}
+
+impl<T: StateData> StateHistory<T> {
+    /// Create an empty history, that will keep at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The state that [`GoBack`] would return to
+    pub fn previous(&self) -> Option<&T> {
+        self.entries.back().map(|entry| &entry.state)
+    }
+
+    /// Iterate over the entries, from the most recent to the oldest
+    pub fn iter(&self) -> impl Iterator<Item = &StateHistoryEntry<T>> {
+        self.entries.iter().rev()
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the history empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The maximum number of entries
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Forget all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn push(&mut self, state: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(StateHistoryEntry {
+            state,
+            exited_at: Instant::now(),
+        });
+    }
+}
+
+/// Insert this as a resource to transition back to the previous state
+///
+/// The previous state is taken from the [`StateHistory`] (and removed from it,
+/// once the transition is accepted), which must be enabled on the
+/// [`StateTransitionStage`]. If the history is empty, nothing happens.
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct GoBack<T>(PhantomData<T>);
+
+impl<T> GoBack<T> {
+    /// Create a new request to go back to the previous state
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for GoBack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What should a [`StateTransitionStage`] do if the requested next state is the same as the current state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SameStatePolicy {
+    /// Perform a full transition: run the exit stage, then the enter stage
+    ///
+    /// Useful for things like restarting a level.
+    #[default]
+    Restart,
+    /// Do nothing
+    Ignore,
+    /// Only run the re-enter stage (if any), instead of the exit and enter stages
+    ///
+    /// See [`StateTransitionStage::add_reenter_system`].
+    Reenter,
+}
+
+/// Event sent when a [`StateTransitionStage`] exceeds its maximum number of transitions
+///
+/// This usually indicates a bug: enter/exit systems that keep requesting
+/// new states, in a cycle. The last requested state is discarded.
+///
+/// The event is only sent if you add it to your app (`app.add_event::<StateTransitionLoop<T>>()`).
+/// Otherwise, the stage will panic.
+#[derive(Debug, Clone)]
+pub struct StateTransitionLoop<T> {
+    /// The sequence of states transitioned through, starting with the original state
+    ///
+    /// The last element is the state that was requested, but discarded.
+    pub states: Vec<T>,
+}
+
+/// Error returned by the `try_*` state extension methods for `App` and `Schedule`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateStageError {
+    /// There is no `StateTransitionStage` for the state type (with the auto-added label)
+    StageNotFound {
+        /// The name of the state type
+        state_type: &'static str,
+    },
+    /// The enter/exit stage of the state is a custom stage, not a `SystemStage`,
+    /// so systems cannot be added to it
+    NotASystemStage {
+        /// The name of the state type
+        state_type: &'static str,
+    },
+}
+
+impl std::fmt::Display for StateStageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateStageError::StageNotFound { state_type } => {
+                write!(f, "State Transition Stage for state type {} not found", state_type)
+            }
+            StateStageError::NotASystemStage { state_type } => {
+                write!(f, "State enter/exit stage for state type {} is not a SystemStage", state_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateStageError {}
+
 #[cfg(feature = "bevy-inspector-egui")]
 impl<T: bevy_inspector_egui::Inspectable> bevy_inspector_egui::Inspectable for CurrentState<T> {
     type Attributes = T::Attributes;
@@ -49,13 +373,16 @@ impl<T: bevy_inspector_egui::Inspectable> bevy_inspector_egui::Inspectable for N
 /// an enter or exit stage for every state value, just the ones you care about.
 ///
 /// When this stage runs, it will check if a [`NextState`] resource exists.
-/// If it does, and its value is different from what's in [`CurrentState`],
-/// this stage will perform a state transition:
+/// If it does, this stage will perform a state transition:
 ///  1. remove the `NextState` resource
 ///  2. run the exit stage (if any) for the current state
 ///  3. change the value of `CurrentState`
 ///  4. run the enter stage (if any) for the next stage
 ///
+/// If the value of `NextState` is the same as `CurrentState`, what happens
+/// depends on the [`SameStatePolicy`]. By default, the state is "restarted":
+/// a full transition is performed, as described above.
+///
 /// This stage manages the [`CurrentState`] resource. It will initialize it if it
 /// doesn't exist, and update it on state transitions.
 ///
@@ -64,13 +391,78 @@ impl<T: bevy_inspector_egui::Inspectable> bevy_inspector_egui::Inspectable for N
 ///
 /// A single run of this stage can execute multiple transitions, if you insert a
 /// new instance of `NextState` from within the exit or enter stages.
+///
+/// If there is no `NextState`, but there is a [`GoBack`] resource, the stage
+/// will transition to the previous state, recorded in the [`StateHistory`]
+/// (if enabled). Otherwise, if there is a [`StateQueue`] resource with
+/// queued states, they will be used as the next states, in order.
+///
+/// To protect against infinite loops, the number of transitions in a single
+/// run is limited (see [`set_max_transitions`](Self::set_max_transitions)).
+/// If the limit is exceeded, the stage panics, or sends a [`StateTransitionLoop`]
+/// event, if you have added that event type to your app.
+///
+/// The state can be made a "sub-state" of another state type, using
+/// [`add_sub_state`](Self::add_sub_state). It will then only exist
+/// while the parent state has a specific value.
+///
+/// The state can also be "computed" (derived from other data in the `World`),
+/// if the stage is created using [`computed`](Self::computed).
+///
+/// To apply transitions at multiple points in the frame, create additional
+/// stages using [`checkpoint`](Self::checkpoint). They share everything
+/// with this stage.
 pub struct StateTransitionStage<T: StateData> {
+    transitions: Arc<Mutex<StateTransitions<T>>>,
+}
+
+/// The data of a [`StateTransitionStage`], shared with its checkpoints
+struct StateTransitions<T: StateData> {
     /// The enter schedules of each state
-    enter_stages: HashMap<T, Box<dyn Stage>>,
+    enter_stages: HashMap<T, Vec<Box<dyn Stage>>>,
     /// The exit schedules of each state
-    exit_stages: HashMap<T, Box<dyn Stage>>,
-    /// The starting state value
-    default: T,
+    exit_stages: HashMap<T, Vec<Box<dyn Stage>>>,
+    /// Enter schedules for any state that matches a predicate
+    enter_matching: Vec<(MatchFn<T>, SystemStage)>,
+    /// Exit schedules for any state that matches a predicate
+    exit_matching: Vec<(MatchFn<T>, SystemStage)>,
+    /// The re-enter schedules of each state (for `SameStatePolicy::Reenter`)
+    reenter_stages: HashMap<T, Box<dyn Stage>>,
+    /// What to do when transitioning to the same state
+    same_state: SameStatePolicy,
+    /// The starting state value (`None` for computed states)
+    default: Option<T>,
+    /// If this is a sub-state: checks if the parent state is active
+    parent: Option<ParentCheck>,
+    /// The sub-states of this state
+    sub_states: Vec<SubState<T>>,
+    /// If this is a computed state: derives the state value from the `World`
+    compute: Option<ComputeFn<T>>,
+    /// How many transitions are allowed in a single run
+    max_transitions: usize,
+    /// The capacity of the `StateHistory`, if enabled
+    history: Option<usize>,
+    /// Resources to insert on enter and remove on exit, for each state
+    scoped_resources: HashMap<T, Vec<ScopedResource>>,
+    /// Should we despawn `StateScoped` entities on exit?
+    #[cfg(feature = "state-scoped")]
+    despawn_scoped: bool,
+    /// If enabled: the values of the state type, to check for missing enter/exit stages on the first run
+    coverage_check: Option<fn() -> Vec<T>>,
+    /// If set: how long transitions take (see the [`timed`] module)
+    #[cfg(feature = "timed")]
+    transition_duration: Option<Duration>,
+    /// Systems to run while a timed transition is in progress
+    #[cfg(feature = "timed")]
+    transition_stage: SystemStage,
+    /// Systems that must return `true` before entering each state
+    readiness: HashMap<T, Vec<readiness::ReadinessSystem>>,
+    /// If tracking is enabled: how many times each transition has happened
+    observed: Option<HashMap<(T, T), u64>>,
+    /// The declared allowed transitions (if none, all transitions are allowed)
+    allowed: HashSet<(T, T)>,
+    /// The states that can be entered from any state
+    allowed_any_from: HashSet<T>,
 }
 
 impl<T: StateData> StateTransitionStage<T> {
@@ -79,21 +471,225 @@ impl<T: StateData> StateTransitionStage<T> {
     /// The provided value is the one that will be used to initialize the
     /// `CurrentState<T>` resource if it is missing.
     pub fn new(default: T) -> Self {
+        Self::from_transitions(Some(default), None)
+    }
+
+    /// Create a new transitions stage for a computed state type
+    ///
+    /// The provided function will be called every time the stage runs, to
+    /// derive the value of the state from other data in the `World` (such as
+    /// the values of other states). If the value changes, a state transition
+    /// is performed, running the exit/enter stages as usual. If the function
+    /// returns `None`, the `CurrentState<T>` resource is removed (after running
+    /// the exit stage for its last value).
+    ///
+    /// The state is fully managed by this stage. Any [`NextState`] resource for
+    /// this state type is ignored.
+    pub fn computed(compute: impl Fn(&World) -> Option<T> + Send + Sync + 'static) -> Self {
+        Self::from_transitions(None, Some(Box::new(compute)))
+    }
+
+    /// Create another stage that checks for and performs transitions of the same state type
+    ///
+    /// The new stage (a "checkpoint") shares everything with this stage: the
+    /// enter/exit stages, configuration, etc. Any changes made to either stage
+    /// also apply to the other. Add it to a different place in your schedule,
+    /// so that state transitions can happen at multiple points in the frame.
+    pub fn checkpoint(&self) -> Self {
         Self {
+            transitions: self.transitions.clone(),
+        }
+    }
+
+    /// Shared by `new` and `computed`; only the initialization of the state differs
+    fn from_transitions(default: Option<T>, compute: Option<ComputeFn<T>>) -> Self {
+        let transitions = StateTransitions {
             enter_stages: Default::default(),
             exit_stages: Default::default(),
+            enter_matching: Vec::new(),
+            exit_matching: Vec::new(),
+            reenter_stages: Default::default(),
+            same_state: SameStatePolicy::Restart,
             default,
+            parent: None,
+            sub_states: Vec::new(),
+            compute,
+            max_transitions: DEFAULT_MAX_TRANSITIONS,
+            history: None,
+            scoped_resources: Default::default(),
+            #[cfg(feature = "state-scoped")]
+            despawn_scoped: false,
+            coverage_check: None,
+            #[cfg(feature = "timed")]
+            transition_duration: None,
+            #[cfg(feature = "timed")]
+            transition_stage: SystemStage::parallel(),
+            readiness: Default::default(),
+            observed: None,
+            allowed: Default::default(),
+            allowed_any_from: Default::default(),
+        };
+        Self {
+            transitions: Arc::new(Mutex::new(transitions)),
         }
     }
 
+    fn transitions(&self) -> MutexGuard<'_, StateTransitions<T>> {
+        self.transitions.lock()
+            .expect("State transition stage poisoned (a system panicked during a transition)")
+    }
+
+    /// Set the maximum number of transitions that may be performed in a single run of the stage
+    ///
+    /// Exceeding it is treated as an error (an infinite transition loop).
+    /// The default is 100.
+    ///
+    /// The limit applies to each chain of transitions requested by systems;
+    /// every transition taken from a [`StateQueue`] starts a new chain.
+    pub fn set_max_transitions(&mut self, max_transitions: usize) {
+        self.transitions().max_transitions = max_transitions;
+    }
+
+    /// Builder version of `set_max_transitions`
+    pub fn with_max_transitions(mut self, max_transitions: usize) -> Self {
+        self.set_max_transitions(max_transitions);
+        self
+    }
+
+    /// Keep a record of previous states in a [`StateHistory`] resource
+    ///
+    /// At most `capacity` entries will be kept. This is needed for [`GoBack`] to work.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.transitions().history = Some(capacity);
+    }
+
+    /// Builder version of `enable_history`
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.enable_history(capacity);
+        self
+    }
+
+    /// Add a resource that should only exist while in the given state
+    ///
+    /// The resource is inserted (using the provided function to create its value)
+    /// when entering the state, before the enter stage runs, and removed when
+    /// exiting the state, after the exit stage runs. This means that all the
+    /// enter/exit systems, and any systems that `run_in_state(state)`, can
+    /// safely access it.
+    pub fn add_scoped_resource<R: Resource>(&mut self, state: T, init: impl Fn() -> R + Send + Sync + 'static) {
+        self.transitions().scoped_resources.entry(state).or_default().push(ScopedResource {
+            insert: Box::new(move |world: &mut World| world.insert_resource(init())),
+            remove: Box::new(|world: &mut World| { world.remove_resource::<R>(); }),
+        });
+    }
+
+    /// Builder version of `add_scoped_resource`
+    pub fn with_scoped_resource<R: Resource>(mut self, state: T, init: impl Fn() -> R + Send + Sync + 'static) -> Self {
+        self.add_scoped_resource(state, init);
+        self
+    }
+
+    /// Automatically despawn entities with a [`StateScoped`] component when exiting their state
+    ///
+    /// The entities (and all their children) are despawned after the exit stage has run.
+    #[cfg(feature = "state-scoped")]
+    pub fn enable_state_scoped_entities(&mut self) {
+        self.transitions().despawn_scoped = true;
+    }
+
+    /// Builder version of `enable_state_scoped_entities`
+    #[cfg(feature = "state-scoped")]
+    pub fn with_state_scoped_entities(mut self) -> Self {
+        self.enable_state_scoped_entities();
+        self
+    }
+
+    /// Make another state type a sub-state of this one
+    ///
+    /// The `CurrentState<S>` resource will only exist while `CurrentState<T>`
+    /// has the value `parent`. This is done as part of the transitions of this
+    /// stage: when `parent` is entered, `CurrentState<S>` is initialized with
+    /// the default value of `sub` (running its enter stage), after the enter
+    /// stage of `parent`. When `parent` is exited, the exit stage for the current
+    /// value of the sub-state runs, and `CurrentState<S>` is removed, before
+    /// the exit stage of `parent`.
+    ///
+    /// Also calls [`set_parent_state`](Self::set_parent_state) on `sub`.
+    /// The `sub` stage must be placed after this stage in the schedule
+    /// (as done by the `add_loopless_sub_state` methods), to perform the
+    /// transitions of the sub-state itself.
+    pub fn add_sub_state<S: StateData>(&mut self, parent: T, sub: &mut StateTransitionStage<S>) {
+        sub.set_parent_state(parent.clone());
+        let set_up = sub.transitions.clone();
+        let tear_down = sub.transitions.clone();
+        self.transitions().sub_states.push(SubState {
+            parent,
+            set_up: Box::new(move |world: &mut World| {
+                set_up.lock()
+                    .expect("State transition stage poisoned (a system panicked during a transition)")
+                    .set_up(world);
+            }),
+            tear_down: Box::new(move |world: &mut World| {
+                tear_down.lock()
+                    .expect("State transition stage poisoned (a system panicked during a transition)")
+                    .tear_down(world);
+            }),
+        });
+    }
+
+    /// Builder version of `add_sub_state`
+    pub fn with_sub_state<S: StateData>(mut self, parent: T, sub: &mut StateTransitionStage<S>) -> Self {
+        self.add_sub_state(parent, sub);
+        self
+    }
+
+    /// Make this state only exist while another state type has a given value
+    ///
+    /// The `CurrentState<T>` resource will only exist while `CurrentState<P>`
+    /// has the value `parent`. This is only checked when this stage runs: the
+    /// sub-state is initialized/torn down then, after the parent's transition
+    /// has already completed.
+    ///
+    /// Prefer [`add_sub_state`](Self::add_sub_state) on the parent's stage,
+    /// which also sets up/tears down the sub-state during the parent's transitions.
+    pub fn set_parent_state<P: StateData>(&mut self, parent: P) {
+        self.transitions().parent = Some(Box::new(move |world: &World| {
+            world.get_resource::<CurrentState<P>>()
+                .map(|current| current.0 == parent)
+                .unwrap_or(false)
+        }));
+    }
+
+    /// Builder version of `set_parent_state`
+    pub fn with_parent_state<P: StateData>(mut self, parent: P) -> Self {
+        self.set_parent_state(parent);
+        self
+    }
+
     /// Provide the stage to run when entering the given state
+    ///
+    /// Replaces all existing enter sub-stages for the state.
     pub fn set_enter_stage<S: Stage>(&mut self, state: T, stage: S) {
-        self.enter_stages.insert(state, Box::new(stage));
+        self.transitions().enter_stages.insert(state, vec![Box::new(stage)]);
     }
 
     /// Provide the stage to run when exiting the given state
+    ///
+    /// Replaces all existing exit sub-stages for the state.
     pub fn set_exit_stage<S: Stage>(&mut self, state: T, stage: S) {
-        self.exit_stages.insert(state, Box::new(stage));
+        self.transitions().exit_stages.insert(state, vec![Box::new(stage)]);
+    }
+
+    /// Provide the stage to run when re-entering the given state
+    ///
+    /// Only used with [`SameStatePolicy::Reenter`].
+    pub fn set_reenter_stage<S: Stage>(&mut self, state: T, stage: S) {
+        self.transitions().reenter_stages.insert(state, Box::new(stage));
+    }
+
+    /// Set what should happen when the requested next state is the same as the current state
+    pub fn set_same_state_policy(&mut self, policy: SameStatePolicy) {
+        self.transitions().same_state = policy;
     }
 
     /// Builder version of `set_enter_stage`
@@ -108,23 +704,27 @@ impl<T: StateData> StateTransitionStage<T> {
         self
     }
 
+    /// Builder version of `set_reenter_stage`
+    pub fn with_reenter_stage<S: Stage>(mut self, state: T, stage: S) -> Self {
+        self.set_reenter_stage(state, stage);
+        self
+    }
+
+    /// Builder version of `set_same_state_policy`
+    pub fn with_same_state_policy(mut self, policy: SameStatePolicy) -> Self {
+        self.set_same_state_policy(policy);
+        self
+    }
+
     /// Add a system to run when entering the given state
     ///
     /// Does not work if you have set a custom enter stage
     /// of type other than `SystemStage`.
     ///
     /// Will create the enter `SystemStage` if it does not exist.
+    /// The system is added to the first sub-stage (index `0`).
     pub fn add_enter_system<Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) {
-        if !self.enter_stages.contains_key(&state) {
-            self.set_enter_stage(state.clone(), SystemStage::parallel());
-        }
-
-        let stage = self.enter_stages.get_mut(&state)
-            .expect("No enter stage for state.")
-            .downcast_mut::<SystemStage>()
-            .expect("State enter stage is not a SystemStage");
-
-        stage.add_system(system);
+        self.add_enter_substage_system(state, 0, system);
     }
 
     /// Add a system to run when exiting the given state
@@ -133,17 +733,9 @@ impl<T: StateData> StateTransitionStage<T> {
     /// of type other than `SystemStage`.
     ///
     /// Will create the exit `SystemStage` if it does not exist.
+    /// The system is added to the first sub-stage (index `0`).
     pub fn add_exit_system<Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) {
-        if !self.exit_stages.contains_key(&state) {
-            self.set_exit_stage(state.clone(), SystemStage::parallel());
-        }
-
-        let stage = self.exit_stages.get_mut(&state)
-            .expect("No exit stage for state.")
-            .downcast_mut::<SystemStage>()
-            .expect("State exit stage is not a SystemStage");
-
-        stage.add_system(system);
+        self.add_exit_substage_system(state, 0, system);
     }
 
     /// Add a system set with multiple systems to run when entering the given state
@@ -155,17 +747,9 @@ impl<T: StateData> StateTransitionStage<T> {
     /// of type other than `SystemStage`.
     ///
     /// Will create the enter `SystemStage` if it does not exist.
+    /// The systems are added to the first sub-stage (index `0`).
     pub fn add_enter_system_set(&mut self, state: T, system_set: SystemSet) {
-        if !self.enter_stages.contains_key(&state) {
-            self.set_enter_stage(state.clone(), SystemStage::parallel());
-        }
-
-        let stage = self.enter_stages.get_mut(&state)
-            .expect("No enter stage for state.")
-            .downcast_mut::<SystemStage>()
-            .expect("State enter stage is not a SystemStage");
-
-        stage.add_system_set(system_set);
+        self.add_enter_substage_system_set(state, 0, system_set);
     }
 
     /// Add a system set with multiple systems to run when exiting the given state
@@ -177,15 +761,149 @@ impl<T: StateData> StateTransitionStage<T> {
     /// of type other than `SystemStage`.
     ///
     /// Will create the exit `SystemStage` if it does not exist.
+    /// The systems are added to the first sub-stage (index `0`).
     pub fn add_exit_system_set(&mut self, state: T, system_set: SystemSet) {
-        if !self.exit_stages.contains_key(&state) {
-            self.set_exit_stage(state.clone(), SystemStage::parallel());
+        self.add_exit_substage_system_set(state, 0, system_set);
+    }
+
+    /// Add a new enter sub-stage for the given state
+    ///
+    /// Sub-stages run in order, and `Commands` from one sub-stage are applied
+    /// before the next one runs. The new stage is a Bevy parallel `SystemStage`,
+    /// added at the end, after any sub-stages that already exist (creating
+    /// the first one, index `0`, if there are none yet).
+    pub fn add_enter_child_stage(&mut self, state: T) {
+        self.add_enter_custom_child_stage(state, SystemStage::parallel());
+    }
+
+    /// Add a custom enter sub-stage for the given state
+    ///
+    /// Like [`add_enter_child_stage`](Self::add_enter_child_stage), but you can provide any stage type.
+    pub fn add_enter_custom_child_stage<S: Stage>(&mut self, state: T, stage: S) {
+        self.transitions().enter_stages.entry(state)
+            .or_insert_with(|| vec![Box::new(SystemStage::parallel())])
+            .push(Box::new(stage));
+    }
+
+    /// Add a system to the given enter sub-stage of the given state
+    ///
+    /// `substage_i` is the index of the sub-stage (`0` if you have not added
+    /// any additional sub-stages).
+    pub fn add_enter_substage_system<Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) {
+        let mut transitions = self.transitions();
+        system_substage(&mut transitions.enter_stages, state, substage_i, "enter")
+            .add_system(system);
+    }
+
+    /// Add a system set to the given enter sub-stage of the given state
+    ///
+    /// `substage_i` is the index of the sub-stage (`0` if you have not added
+    /// any additional sub-stages).
+    pub fn add_enter_substage_system_set(&mut self, state: T, substage_i: usize, system_set: SystemSet) {
+        let mut transitions = self.transitions();
+        system_substage(&mut transitions.enter_stages, state, substage_i, "enter")
+            .add_system_set(system_set);
+    }
+
+    /// Add a new exit sub-stage for the given state
+    ///
+    /// Sub-stages run in order, and `Commands` from one sub-stage are applied
+    /// before the next one runs. The new stage is a Bevy parallel `SystemStage`,
+    /// added at the end, after any sub-stages that already exist (creating
+    /// the first one, index `0`, if there are none yet).
+    pub fn add_exit_child_stage(&mut self, state: T) {
+        self.add_exit_custom_child_stage(state, SystemStage::parallel());
+    }
+
+    /// Add a custom exit sub-stage for the given state
+    ///
+    /// Like [`add_exit_child_stage`](Self::add_exit_child_stage), but you can provide any stage type.
+    pub fn add_exit_custom_child_stage<S: Stage>(&mut self, state: T, stage: S) {
+        self.transitions().exit_stages.entry(state)
+            .or_insert_with(|| vec![Box::new(SystemStage::parallel())])
+            .push(Box::new(stage));
+    }
+
+    /// Add a system to the given exit sub-stage of the given state
+    ///
+    /// `substage_i` is the index of the sub-stage (`0` if you have not added
+    /// any additional sub-stages).
+    pub fn add_exit_substage_system<Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) {
+        let mut transitions = self.transitions();
+        system_substage(&mut transitions.exit_stages, state, substage_i, "exit")
+            .add_system(system);
+    }
+
+    /// Add a system set to the given exit sub-stage of the given state
+    ///
+    /// `substage_i` is the index of the sub-stage (`0` if you have not added
+    /// any additional sub-stages).
+    pub fn add_exit_substage_system_set(&mut self, state: T, substage_i: usize, system_set: SystemSet) {
+        let mut transitions = self.transitions();
+        system_substage(&mut transitions.exit_stages, state, substage_i, "exit")
+            .add_system_set(system_set);
+    }
+
+    /// Add a system to run when entering any state that matches the predicate
+    ///
+    /// Useful for states that carry data, like `matches!(state, GameState::InGame { .. })`.
+    /// Runs after the enter stages of the exact state. Every call creates a separate stage.
+    pub fn add_enter_system_matching<Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) {
+        let mut stage = SystemStage::parallel();
+        stage.add_system(system);
+        self.transitions().enter_matching.push((Box::new(predicate), stage));
+    }
+
+    /// Add a system to run when exiting any state that matches the predicate
+    ///
+    /// Useful for states that carry data, like `matches!(state, GameState::InGame { .. })`.
+    /// Runs after the exit stages of the exact state. Every call creates a separate stage.
+    pub fn add_exit_system_matching<Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) {
+        let mut stage = SystemStage::parallel();
+        stage.add_system(system);
+        self.transitions().exit_matching.push((Box::new(predicate), stage));
+    }
+
+    /// Add a system to run when re-entering the given state
+    ///
+    /// Only used with [`SameStatePolicy::Reenter`].
+    ///
+    /// Does not work if you have set a custom re-enter stage
+    /// of type other than `SystemStage`.
+    ///
+    /// Will create the re-enter `SystemStage` if it does not exist.
+    pub fn add_reenter_system<Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) {
+        if !self.transitions().reenter_stages.contains_key(&state) {
+            self.set_reenter_stage(state.clone(), SystemStage::parallel());
+        }
+
+        let mut transitions = self.transitions();
+        let stage = transitions.reenter_stages.get_mut(&state)
+            .expect("No re-enter stage for state.")
+            .downcast_mut::<SystemStage>()
+            .expect("State re-enter stage is not a SystemStage");
+
+        stage.add_system(system);
+    }
+
+    /// Add a system set with multiple systems to run when re-entering the given state
+    ///
+    /// Only used with [`SameStatePolicy::Reenter`].
+    ///
+    /// Does not work if you have set a custom re-enter stage
+    /// of type other than `SystemStage`.
+    ///
+    /// Will create the re-enter `SystemStage` if it does not exist.
+    pub fn add_reenter_system_set(&mut self, state: T, system_set: SystemSet) {
+        if !self.transitions().reenter_stages.contains_key(&state) {
+            self.set_reenter_stage(state.clone(), SystemStage::parallel());
         }
 
-        let stage = self.exit_stages.get_mut(&state)
-            .expect("No exit stage for state.")
+        let mut transitions = self.transitions();
+        let stage = transitions.reenter_stages.get_mut(&state)
+            .expect("No re-enter stage for state.")
             .downcast_mut::<SystemStage>()
-            .expect("State exit stage is not a SystemStage");
+            .expect("State re-enter stage is not a SystemStage");
 
         stage.add_system_set(system_set);
     }
@@ -202,6 +920,18 @@ impl<T: StateData> StateTransitionStage<T> {
         self
     }
 
+    /// Builder version of `add_enter_system_matching`
+    pub fn with_enter_system_matching<Params>(mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.add_enter_system_matching(predicate, system);
+        self
+    }
+
+    /// Builder version of `add_exit_system_matching`
+    pub fn with_exit_system_matching<Params>(mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.add_exit_system_matching(predicate, system);
+        self
+    }
+
     /// Builder version of `add_enter_system_set`
     pub fn with_enter_system_set(mut self, state: T, system_set: SystemSet) -> Self {
         self.add_enter_system_set(state, system_set);
@@ -213,37 +943,305 @@ impl<T: StateData> StateTransitionStage<T> {
         self.add_exit_system_set(state, system_set);
         self
     }
-}
 
-impl<T: StateData> Stage for StateTransitionStage<T> {
-    fn run(&mut self, world: &mut World) {
-        loop {
-            let current = if let Some(res) = world.get_resource::<CurrentState<T>>() {
+    /// Builder version of `add_reenter_system`
+    pub fn with_reenter_system<Params>(mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.add_reenter_system(state, system);
+        self
+    }
+
+    /// Builder version of `add_reenter_system_set`
+    pub fn with_reenter_system_set(mut self, state: T, system_set: SystemSet) -> Self {
+        self.add_reenter_system_set(state, system_set);
+        self
+    }
+}
+
+impl<T: StateData> StateTransitionStage<T> {
+    /// Can systems be added to the enter stage of the given state?
+    pub(crate) fn check_enter_system_stage(&self, state: &T) -> Result<(), StateStageError> {
+        check_system_substage(&self.transitions().enter_stages, state)
+    }
+
+    /// Can systems be added to the exit stage of the given state?
+    pub(crate) fn check_exit_system_stage(&self, state: &T) -> Result<(), StateStageError> {
+        check_system_substage(&self.transitions().exit_stages, state)
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    /// Do everything needed when entering a state
+    fn enter_state(&mut self, world: &mut World, state: &T) {
+        #[cfg(feature = "testing")]
+        crate::testing::StateRecorder::record(world, crate::testing::StateRecord::Enter(state.clone()));
+        if let Some(resources) = self.scoped_resources.get(state) {
+            for resource in resources {
+                (resource.insert)(world);
+            }
+        }
+        if let Some(stages) = self.enter_stages.get_mut(state) {
+            for stage in stages.iter_mut() {
+                stage.run(world);
+            }
+        }
+        for (predicate, stage) in self.enter_matching.iter_mut() {
+            if predicate(state) {
+                stage.run(world);
+            }
+        }
+        for sub_state in self.sub_states.iter().filter(|sub_state| sub_state.parent == *state) {
+            (sub_state.set_up)(world);
+        }
+    }
+
+    /// Exit the current state and enter the next one
+    fn transition(&mut self, world: &mut World, current: &T, next: &T, going_back: bool) {
+        self.observe_transition(current, next);
+        self.exit_state(world, current);
+        if let (Some(capacity), false) = (self.history, going_back || current == next) {
+            world.get_resource_or_insert_with(|| StateHistory::new(capacity))
+                .push(current.clone());
+        }
+        world.insert_resource(CurrentState(next.clone()));
+        self.enter_state(world, next);
+    }
+
+    /// Do everything needed when exiting a state
+    fn exit_state(&mut self, world: &mut World, state: &T) {
+        #[cfg(feature = "testing")]
+        crate::testing::StateRecorder::record(world, crate::testing::StateRecord::Exit(state.clone()));
+        for sub_state in self.sub_states.iter().rev().filter(|sub_state| sub_state.parent == *state) {
+            (sub_state.tear_down)(world);
+        }
+        if let Some(stages) = self.exit_stages.get_mut(state) {
+            for stage in stages.iter_mut() {
+                stage.run(world);
+            }
+        }
+        for (predicate, stage) in self.exit_matching.iter_mut() {
+            if predicate(state) {
+                stage.run(world);
+            }
+        }
+        #[cfg(feature = "state-scoped")]
+        if self.despawn_scoped {
+            despawn_state_scoped(world, state);
+        }
+        if let Some(resources) = self.scoped_resources.get(state) {
+            for resource in resources {
+                (resource.remove)(world);
+            }
+        }
+    }
+
+    /// Initialize the state, if it does not exist
+    fn set_up(&mut self, world: &mut World) {
+        if world.contains_resource::<CurrentState<T>>() {
+            return;
+        }
+        let init = match &self.compute {
+            Some(compute) => compute(world),
+            None => self.default.clone(),
+        };
+        if let Some(init) = init {
+            world.insert_resource(CurrentState(init.clone()));
+            self.enter_state(world, &init);
+        }
+    }
+
+    /// Exit the current state and remove it (and any requests), if it exists
+    fn tear_down(&mut self, world: &mut World) {
+        let current = world.get_resource::<CurrentState<T>>()
+            .map(|res| res.0.clone());
+        if let Some(current) = current {
+            self.exit_state(world, &current);
+            world.remove_resource::<CurrentState<T>>();
+        }
+        world.remove_resource::<NextState<T>>();
+        #[cfg(feature = "timed")]
+        world.remove_resource::<timed::Transitioning<T>>();
+        world.remove_resource::<readiness::PendingTransition<T>>();
+    }
+}
+
+impl<T: StateData> Stage for StateTransitionStage<T> {
+    fn run(&mut self, world: &mut World) {
+        self.transitions().run(world);
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    fn run(&mut self, world: &mut World) {
+        if let Some(variants) = self.coverage_check.take() {
+            self.check_coverage(world, variants());
+        }
+
+        // the value to initialize the state with, if it should exist at all
+        let init = if let Some(compute) = &self.compute {
+            // computed states are not changed manually
+            world.remove_resource::<NextState<T>>();
+            compute(world)
+        } else if self.parent.as_ref().map(|parent| parent(world)).unwrap_or(true) {
+            self.default.clone()
+        } else {
+            None
+        };
+
+        let init = match init {
+            Some(init) => init,
+            None => {
+                // the state should not exist; tear it down, if it does
+                self.tear_down(world);
+                return;
+            }
+        };
+
+        if self.compute.is_some() {
+            let changed = world.get_resource::<CurrentState<T>>()
+                .map(|current| current.0 != init)
+                .unwrap_or(false);
+            if changed {
+                world.insert_resource(NextState(init.clone()));
+            }
+        }
+
+        if let Some(capacity) = self.history {
+            if !world.contains_resource::<StateHistory<T>>() {
+                world.insert_resource(StateHistory::<T>::new(capacity));
+            }
+        }
+
+        // any new requests stay pending until a timed transition is complete
+        #[cfg(feature = "timed")]
+        if self.update_timed_transition(world) {
+            return;
+        }
+
+        // how many states to take from the queue; states queued during this run wait for the next one
+        let mut queue_budget = match world.get_resource::<StateQueue<T>>() {
+            Some(_) if self.compute.is_some() => 0,
+            Some(queue) if queue.one_per_frame => 1,
+            Some(queue) => queue.queue.len(),
+            None => 0,
+        };
+        // the states we have transitioned through, for reporting loops
+        let mut path = Vec::new();
+
+        loop {
+            let current = if let Some(res) = world.get_resource::<CurrentState<T>>() {
                 res.0.clone()
             } else {
                 // first run; gotta run the initial enter stage
-                world.insert_resource(CurrentState(self.default.clone()));
-                if let Some(stage) = self.enter_stages.get_mut(&self.default) {
-                    stage.run(world);
-                }
+                world.insert_resource(CurrentState(init.clone()));
+                self.enter_state(world, &init);
                 world
-                    .get_resource_or_insert_with(|| CurrentState(self.default.clone()))
+                    .get_resource_or_insert_with(|| CurrentState(init.clone()))
                     .0
                     .clone()
             };
 
-            let next = world.remove_resource::<NextState<T>>();
+            let mut next = world.remove_resource::<NextState<T>>()
+                .map(|NextState(next)| next);
+            world.remove_resource::<commands::StateRequestOrigin<T>>();
+            // requests that skip the allowed transitions check (like restoring a snapshot)
+            let unchecked = world.remove_resource::<allowed::UncheckedRequest<T>>()
+                .map(|unchecked| next.as_ref() == Some(&unchecked.0))
+                .unwrap_or(false);
+
+            // a request waiting for readiness; replaced by any new request,
+            // otherwise left in place until it is performed or discarded
+            let mut going_back = false;
+            let mut from_pending = false;
+            if next.is_some() {
+                world.remove_resource::<readiness::PendingTransition<T>>();
+            } else if let Some(pending) = world.get_resource::<readiness::PendingTransition<T>>() {
+                going_back = pending.going_back;
+                next = Some(pending.to.clone());
+                from_pending = true;
+            }
+            if next.is_none() && world.remove_resource::<GoBack<T>>().is_some() {
+                if self.history.is_none() {
+                    warn!(
+                        "GoBack requested for state type {}, but history is not enabled",
+                        std::any::type_name::<T>(),
+                    );
+                }
+                // the entry is only removed if the transition is accepted
+                next = world.get_resource::<StateHistory<T>>()
+                    .and_then(|history| history.previous().cloned());
+                // with an empty history, anything else (like the queue) is not going back
+                going_back = next.is_some();
+            }
+
+            let mut from_queue = false;
+            if next.is_none() && queue_budget > 0 {
+                if let Some(mut queue) = world.get_resource_mut::<StateQueue<T>>() {
+                    next = queue.queue.pop_front();
+                    from_queue = next.is_some();
+                }
+                queue_budget -= 1;
+            }
+
+            if let Some(next) = next {
+                if next == current && self.same_state == SameStatePolicy::Ignore {
+                    if from_pending {
+                        world.remove_resource::<readiness::PendingTransition<T>>();
+                    }
+                    continue;
+                }
+
+                // pending requests were already checked when they were made
+                if !unchecked && !from_pending && !self.check_allowed(world, &current, &next) {
+                    continue;
+                }
+
+                if !self.check_readiness(world, &next) {
+                    if !from_pending {
+                        world.insert_resource(readiness::PendingTransition {
+                            from: current,
+                            to: next,
+                            going_back,
+                        });
+                    }
+                    break;
+                }
+                if from_pending {
+                    world.remove_resource::<readiness::PendingTransition<T>>();
+                }
+
+                if going_back {
+                    if let Some(mut history) = world.get_resource_mut::<StateHistory<T>>() {
+                        history.entries.pop_back();
+                    }
+                }
 
-            if let Some(NextState(next)) = next {
-                if let Some(stage) = self.exit_stages.get_mut(&current) {
-                    stage.run(world);
+                // the queue is finite, so only requests made by systems can loop
+                if from_queue {
+                    path.clear();
+                }
+                if path.is_empty() {
+                    path.push(current.clone());
+                }
+                path.push(next.clone());
+                if path.len() > self.max_transitions + 1 {
+                    report_transition_loop(world, path);
+                    break;
                 }
 
-                world.insert_resource(CurrentState(next.clone()));
+                if next == current && self.same_state == SameStatePolicy::Reenter {
+                    #[cfg(feature = "testing")]
+                    crate::testing::StateRecorder::record(world, crate::testing::StateRecord::Reenter(current.clone()));
+                    if let Some(stage) = self.reenter_stages.get_mut(&current) {
+                        stage.run(world);
+                    }
+                    continue;
+                }
 
-                if let Some(stage) = self.enter_stages.get_mut(&next) {
-                    stage.run(world);
+                #[cfg(feature = "timed")]
+                if self.start_timed_transition(world, &current, &next, going_back) {
+                    break;
                 }
+                self.transition(world, &current, &next, going_back);
             } else {
                 break;
             }
@@ -251,34 +1249,159 @@ impl<T: StateData> Stage for StateTransitionStage<T> {
     }
 }
 
+const DEFAULT_MAX_TRANSITIONS: usize = 100;
+
+/// Check that the first enter/exit sub-stage of a state is a `SystemStage` (or does not exist yet)
+fn check_system_substage<T: StateData>(stages: &HashMap<T, Vec<Box<dyn Stage>>>, state: &T) -> Result<(), StateStageError> {
+    match stages.get(state).and_then(|stages| stages.first()) {
+        Some(stage) if !stage.is::<SystemStage>() => Err(StateStageError::NotASystemStage {
+            state_type: std::any::type_name::<T>(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Get a `SystemStage` from the enter/exit sub-stages of a state (creating the first one, if needed)
+fn system_substage<'a, T: StateData>(stages: &'a mut HashMap<T, Vec<Box<dyn Stage>>>, state: T, substage_i: usize, kind: &str) -> &'a mut SystemStage {
+    stages.entry(state)
+        .or_insert_with(|| vec![Box::new(SystemStage::parallel())])
+        .get_mut(substage_i)
+        .unwrap_or_else(|| panic!("No {} sub-stage {} for state.", kind, substage_i))
+        .downcast_mut::<SystemStage>()
+        .unwrap_or_else(|| panic!("State {} sub-stage is not a SystemStage", kind))
+}
+
+/// Recursively despawn all entities scoped to the given state
+#[cfg(feature = "state-scoped")]
+fn despawn_state_scoped<T: StateData>(world: &mut World, state: &T) {
+    let entities: Vec<Entity> = world.query::<(Entity, &StateScoped<T>)>()
+        .iter(world)
+        .filter(|(_, scoped)| scoped.0 == *state)
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in entities {
+        bevy_hierarchy::despawn_with_children_recursive(world, entity);
+    }
+}
+
+/// Send a [`StateTransitionLoop`] event if the app has it, otherwise panic
+fn report_transition_loop<T: StateData>(world: &mut World, states: Vec<T>) {
+    // report the cycle that the last state closes (if any), not the whole path
+    let last = states.last().expect("Transition path should not be empty");
+    let cycle_start = states[..states.len() - 1].iter()
+        .rposition(|state| state == last)
+        .unwrap_or(0);
+    let message = format!(
+        "State transition loop detected for state type {}: exceeded {} transitions in a single run (cycle: {})",
+        std::any::type_name::<T>(),
+        states.len() - 2,
+        states[cycle_start..].iter()
+            .map(|state| format!("{:?}", state))
+            .collect::<Vec<_>>()
+            .join(" -> "),
+    );
+    if let Some(mut events) = world.get_resource_mut::<Events<StateTransitionLoop<T>>>() {
+        error!("{}", message);
+        events.send(StateTransitionLoop { states });
+    } else {
+        panic!("{}", message);
+    }
+}
+
+/// Adapt a function computing a state from two other states, for use with [`StateTransitionStage::computed`]
+fn compute_from_states<D: StateData, A: StateData, B: StateData>(
+    compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>,
+) -> impl Fn(&World) -> Option<D> + Send + Sync + 'static {
+    move |world: &World| {
+        let a = world.get_resource::<CurrentState<A>>()?;
+        let b = world.get_resource::<CurrentState<B>>()?;
+        compute(a, b)
+    }
+}
+
 /// Type used as a Bevy Stage Label for state transition stages
-#[derive(Debug, Clone)]
-pub struct StateTransitionStageLabel(TypeId, String);
+///
+/// This is the label used by the `add_loopless_state*` helper methods. You can
+/// also use your own labels, with the `add_labeled_loopless_state*` methods.
+///
+/// Labels only distinguish the stages in the schedule. All stages for the same
+/// state type use the same `CurrentState<T>`/`NextState<T>` resources; keeping
+/// separate state values per label is not supported.
+#[derive(Debug, Clone, Copy)]
+pub struct StateTransitionStageLabel(&'static str);
 
 impl StageLabel for StateTransitionStageLabel {
     fn as_str(&self) -> &'static str {
-        let s = format!("{:?}{}", self.0, self.1);
-        Box::leak(s.into_boxed_str())
+        self.0
     }
 }
 
 impl StateTransitionStageLabel {
     /// Construct the label for a stage to drive the state type T
     pub fn from_type<T: StateData>() -> Self {
-        use std::any::type_name;
-        StateTransitionStageLabel(TypeId::of::<T>(), type_name::<T>().to_owned())
+        StateTransitionStageLabel(type_label::<T>())
+    }
+
+    /// Construct the label for a checkpoint stage of the state type T, placed after the given stage
+    pub fn checkpoint_after<T: StateData>(stage: &impl StageLabel) -> Self {
+        StateTransitionStageLabel(intern_label(format!("{} checkpoint after {}", type_label::<T>(), stage.as_str())))
+    }
+
+    /// Construct the label for a checkpoint stage of the state type T, placed before the given stage
+    pub fn checkpoint_before<T: StateData>(stage: &impl StageLabel) -> Self {
+        StateTransitionStageLabel(intern_label(format!("{} checkpoint before {}", type_label::<T>(), stage.as_str())))
+    }
+}
+
+/// A label string that is unique to the type T
+///
+/// Type names alone are not unique (for example, across versions of the same crate),
+/// so the `TypeId` is included too.
+fn type_label<T: 'static>() -> &'static str {
+    intern_label(format!("{} ({:?})", std::any::type_name::<T>(), std::any::TypeId::of::<T>()))
+}
+
+/// Get a `'static` version of a label string, allocating it only once
+fn intern_label(label: String) -> &'static str {
+    static LABELS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut labels = LABELS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(interned) = labels.iter().find(|interned| **interned == label) {
+        return interned;
     }
+    let interned: &'static str = Box::leak(label.into_boxed_str());
+    labels.push(interned);
+    interned
 }
 
 /// Extensions to `bevy_app`
 #[cfg(feature = "app")]
 pub mod app {
     use bevy_ecs::schedule::{StageLabel, Stage, StateData, IntoSystemDescriptor, SystemSet};
+    use bevy_ecs::system::{IntoSystem, NonSend, Resource};
     use bevy_app::{App, CoreStage};
+    #[cfg(feature = "timed")]
+    use bevy_utils::Duration;
+    use bevy_utils::tracing::warn;
 
-    use super::{StateTransitionStage, StateTransitionStageLabel};
+    use super::coverage::LooplessStates;
+    use super::{CurrentState, SameStatePolicy, StateStageError, StateTransitionStage, StateTransitionStageLabel, compute_from_states};
+    use super::schedule::ScheduleLooplessStateExt;
 
     /// Extension trait with the methods to add to Bevy's `App`
+    ///
+    /// The methods that configure an existing state type (adding enter/exit systems, etc.)
+    /// can be called before the state type is added. The registrations are buffered, and
+    /// applied when its `StateTransitionStage` is added (by the `add_loopless_state*`
+    /// methods), so the order of your plugins does not matter. If the state type is
+    /// never added, they are ignored, and a warning is logged at startup. Use the
+    /// `try_*` methods if you want an error instead.
+    ///
+    /// Methods that add stages relative to the `StateTransitionStage` (like
+    /// `add_loopless_sub_state`, which needs the parent stage, and the
+    /// `add_loopless_state_checkpoint_*` methods) are not buffered, and still panic
+    /// if the state type has not been added yet. Neither are the methods of
+    /// [`ScheduleLooplessStateExt`](super::schedule::ScheduleLooplessStateExt),
+    /// because a `Schedule` has nowhere to store them.
     pub trait AppLooplessStateExt {
         /// Add a `StateTransitionStage` in the default position
         ///
@@ -288,6 +1411,221 @@ pub mod app {
         fn add_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, init: T) -> &mut App;
         /// Add a `StateTransitionStage` before the specified stage
         fn add_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, init: T) -> &mut App;
+        /// Add a `StateTransitionStage` with a custom label, after the specified stage
+        ///
+        /// Use [`get_labeled_loopless_state_stage_mut`](Self::get_labeled_loopless_state_stage_mut)
+        /// with the same label to configure it, or the `add_labeled_enter_system`/`add_labeled_exit_system`
+        /// methods; the other helper methods (and buffered registrations) only work with the default label.
+        ///
+        /// The stages are not independent instances: all stages for the same state type use
+        /// the same `CurrentState<T>` and `NextState<T>` resources. To have separate state values,
+        /// the stages must run on different `World`s (like in a sub-app), or use different state types.
+        fn add_labeled_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut App;
+        /// Add a `StateTransitionStage` with a custom label, before the specified stage
+        ///
+        /// See [`add_labeled_loopless_state_after_stage`](Self::add_labeled_loopless_state_after_stage).
+        fn add_labeled_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut App;
+        /// Get the `StateTransitionStage` with the given label
+        ///
+        /// Panics if it does not exist, or is for a different state type.
+        fn get_labeled_loopless_state_stage_mut<T: StateData>(&mut self, label: impl StageLabel) -> &mut StateTransitionStage<T>;
+        /// Add an enter system to the `StateTransitionStage` with the given label
+        ///
+        /// Panics if it does not exist, or is for a different state type.
+        fn add_labeled_enter_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add an exit system to the `StateTransitionStage` with the given label
+        ///
+        /// Panics if it does not exist, or is for a different state type.
+        fn add_labeled_exit_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a `StateTransitionStage` for a sub-state of another state type
+        ///
+        /// The sub-state only exists while the parent state has the value `parent`.
+        /// The stage is added right after the parent's `StateTransitionStage`.
+        /// The sub-state is set up/torn down during the parent's transitions
+        /// (see `StateTransitionStage::add_sub_state`).
+        ///
+        /// Requires the parent stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_loopless_sub_state<P: StateData, T: StateData>(&mut self, parent: P, init: T) -> &mut App;
+        /// Add a checkpoint for an existing state type, after the specified stage
+        ///
+        /// The checkpoint is another stage that checks for and performs state transitions,
+        /// sharing the enter/exit stages with the main `StateTransitionStage`. This way,
+        /// `NextState` can be applied at multiple points in the frame.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_loopless_state_checkpoint_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App;
+        /// Add a checkpoint for an existing state type, before the specified stage
+        ///
+        /// See [`add_loopless_state_checkpoint_after_stage`](Self::add_loopless_state_checkpoint_after_stage).
+        fn add_loopless_state_checkpoint_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App;
+        /// Add a `StateTransitionStage` for a computed state, in the default position
+        ///
+        /// (before `CoreStage::Update`)
+        ///
+        /// The value of the state is derived from two other state types, using the
+        /// provided function. It is recomputed every time the stage runs. If either
+        /// of the source states does not exist, or the function returns `None`,
+        /// the computed state will not exist either.
+        ///
+        /// Add this after the source state types, so that it runs after their stages.
+        fn add_computed_state<D: StateData, A: StateData, B: StateData>(&mut self, compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>) -> &mut App;
+        /// Add a `StateTransitionStage` for a computed state, after the specified stage
+        ///
+        /// See [`add_computed_state`](Self::add_computed_state).
+        fn add_computed_state_after_stage<D: StateData, A: StateData, B: StateData>(&mut self, stage: impl StageLabel, compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>) -> &mut App;
+        /// Add a re-enter system for the given state
+        ///
+        /// Only used if the `SameStatePolicy` for the state type is `Reenter`.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_reenter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Set what should happen when the requested next state is the same as the current state
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn set_same_state_policy<T: StateData>(&mut self, policy: SameStatePolicy) -> &mut App;
+        /// Keep a record of previous states in a `StateHistory<T>` resource
+        ///
+        /// Needed for `GoBack<T>` to work.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn enable_state_history<T: StateData>(&mut self, capacity: usize) -> &mut App;
+        /// Allow transitions of the given state type from one specific state to another
+        ///
+        /// Once any are declared, other transitions are rejected. See the [`allowed`](super::allowed) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn allow_state_transition<T: StateData>(&mut self, from: T, to: T) -> &mut App;
+        /// Allow transitions from any state to the given state
+        ///
+        /// Once any are declared, other transitions are rejected. See the [`allowed`](super::allowed) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn allow_state_transition_any_from<T: StateData>(&mut self, to: T) -> &mut App;
+        /// Count the transitions of the given state type, to show them in its `StateGraph`
+        ///
+        /// See the [`graph`](super::graph) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn enable_state_transition_tracking<T: StateData>(&mut self) -> &mut App;
+        /// Make transitions of the given state type take the given amount of time
+        ///
+        /// While in progress, a `Transitioning<T>` resource exists. See the [`timed`](super::timed) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        #[cfg(feature = "timed")]
+        fn set_state_transition_duration<T: StateData>(&mut self, duration: Duration) -> &mut App;
+        /// Add a system to run once per frame while a timed transition of the given state type is in progress
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        #[cfg(feature = "timed")]
+        fn add_state_transition_system<T: StateData, Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a system that must return `true` before entering the given state
+        ///
+        /// While waiting, a `PendingTransition<T>` resource exists. See the [`readiness`](super::readiness) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_readiness_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystem<(), bool, Params>) -> &mut App;
+        /// Check which states of the given type have no enter/exit systems, when its stage first runs
+        ///
+        /// Missing ones are logged as a warning. See [`LooplessStates`].
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn enable_state_coverage_check<T: LooplessStates>(&mut self) -> &mut App;
+        /// Add an enter system for all the states that match the filter
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut App;
+        /// Add an exit system for all the states that match the filter
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut App;
+        /// Add a system to run when entering any state that matches the predicate
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a system to run when exiting any state that matches the predicate
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a resource that should only exist while in the given state
+        ///
+        /// It is inserted when entering the state and removed when exiting it.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_scoped_resource<T: StateData, R: Resource>(&mut self, state: T, init: impl Fn() -> R + Send + Sync + 'static) -> &mut App;
+        /// Despawn entities with a `StateScoped<T>` component when exiting their state
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        #[cfg(feature = "state-scoped")]
+        fn enable_state_scoped_entities<T: StateData>(&mut self) -> &mut App;
+        /// Add a new enter sub-stage for the given state
+        ///
+        /// Sub-stages run in order, so `Commands` from systems in one sub-stage
+        /// are applied before the next sub-stage runs. Use `add_enter_substage_system`
+        /// to add systems to it.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_child_stage<T: StateData>(&mut self, state: T) -> &mut App;
+        /// Add a system to the given enter sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a system set to the given enter sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut App;
+        /// Add a new exit sub-stage for the given state
+        ///
+        /// Sub-stages run in order, so `Commands` from systems in one sub-stage
+        /// are applied before the next sub-stage runs. Use `add_exit_substage_system`
+        /// to add systems to it.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_child_stage<T: StateData>(&mut self, state: T) -> &mut App;
+        /// Add a system to the given exit sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a system set to the given exit sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut App;
         /// Add an enter system for the given state
         ///
         /// Requires the stage to be labeled with a `StateTransitionStageLabel`
@@ -322,6 +1660,23 @@ pub mod app {
         ///
         /// Cannot be used together with `add_enter_system`.
         fn set_exit_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> &mut App;
+        /// Get the `StateTransitionStage` for the given state type
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn try_get_loopless_state_stage_mut<T: StateData>(&mut self) -> Result<&mut StateTransitionStage<T>, StateStageError>;
+        /// Like `add_enter_system`, but returns an error if it is not possible
+        fn try_add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut App, StateStageError>;
+        /// Like `add_exit_system`, but returns an error if it is not possible
+        fn try_add_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut App, StateStageError>;
+        /// Like `add_enter_system_set`, but returns an error if it is not possible
+        fn try_add_enter_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut App, StateStageError>;
+        /// Like `add_exit_system_set`, but returns an error if it is not possible
+        fn try_add_exit_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut App, StateStageError>;
+        /// Like `set_enter_stage`, but returns an error if it is not possible
+        fn try_set_enter_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut App, StateStageError>;
+        /// Like `set_exit_stage`, but returns an error if it is not possible
+        fn try_set_exit_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut App, StateStageError>;
     }
 
     impl AppLooplessStateExt for App {
@@ -333,66 +1688,478 @@ pub mod app {
                 stage,
                 StateTransitionStageLabel::from_type::<T>(),
                 StateTransitionStage::new(init)
-            )
+            );
+            apply_pending_registrations::<T>(self)
         }
         fn add_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, init: T) -> &mut App {
             self.add_stage_before(
                 stage,
                 StateTransitionStageLabel::from_type::<T>(),
                 StateTransitionStage::new(init)
-            )
+            );
+            apply_pending_registrations::<T>(self)
         }
-        fn add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
-            let stage = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
-                .expect("State Transition Stage not found (assuming auto-added label)");
-            stage.add_enter_system(state, system);
-            self
+        fn add_labeled_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut App {
+            self.add_stage_after(stage, label, StateTransitionStage::new(init))
         }
-        fn add_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
-            let stage = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
-                .expect("State Transition Stage not found (assuming auto-added label)");
-            stage.add_exit_system(state, system);
-            self
+        fn add_labeled_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut App {
+            self.add_stage_before(stage, label, StateTransitionStage::new(init))
         }
-        fn add_enter_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> &mut App {
-            let stage = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
-                .expect("State Transition Stage not found (assuming auto-added label)");
-            stage.add_enter_system_set(state, system_set);
-            self
+        fn get_labeled_loopless_state_stage_mut<T: StateData>(&mut self, label: impl StageLabel) -> &mut StateTransitionStage<T> {
+            self.schedule.get_stage_mut::<StateTransitionStage<T>>(label)
+                .expect("State Transition Stage not found")
         }
-        fn add_exit_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> &mut App {
-            let stage = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
-                .expect("State Transition Stage not found (assuming auto-added label)");
-            stage.add_exit_system_set(state, system_set);
+        fn add_labeled_enter_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            self.get_labeled_loopless_state_stage_mut::<T>(label)
+                .add_enter_system(state, system);
             self
         }
-        fn set_enter_stage<T: StateData>(&mut self, state: T, enter_stage: impl Stage) -> &mut App {
-            let stage = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
-                .expect("State Transition Stage not found (assuming auto-added label)");
-            stage.set_enter_stage(state, enter_stage);
+        fn add_labeled_exit_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            self.get_labeled_loopless_state_stage_mut::<T>(label)
+                .add_exit_system(state, system);
             self
         }
-        fn set_exit_stage<T: StateData>(&mut self, state: T, exit_stage: impl Stage) -> &mut App {
-            let stage = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
-                .expect("State Transition Stage not found (assuming auto-added label)");
-            stage.set_exit_stage(state, exit_stage);
-            self
+        fn add_loopless_sub_state<P: StateData, T: StateData>(&mut self, parent: P, init: T) -> &mut App {
+            let mut stage = StateTransitionStage::new(init);
+            self.schedule.get_stage_mut::<StateTransitionStage<P>>(StateTransitionStageLabel::from_type::<P>())
+                .expect("State Transition Stage not found (assuming auto-added label)")
+                .add_sub_state(parent, &mut stage);
+            self.add_stage_after(
+                StateTransitionStageLabel::from_type::<P>(),
+                StateTransitionStageLabel::from_type::<T>(),
+                stage
+            );
+            apply_pending_registrations::<T>(self)
         }
-    }
-}
-
-/// Extensions to Bevy Schedule
-pub mod schedule {
-    use bevy_ecs::schedule::{StageLabel, Stage, StateData, IntoSystemDescriptor, SystemSet, Schedule};
-
-    use super::{StateTransitionStage, StateTransitionStageLabel};
-
-    /// Extension trait with the methods to add to Bevy's `Schedule`
-    pub trait ScheduleLooplessStateExt {
-        /// Add a `StateTransitionStage` after the specified stage
-        fn add_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, init: T) -> &mut Schedule;
+        fn add_loopless_state_checkpoint_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App {
+            let checkpoint = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)")
+                .checkpoint();
+            let label = StateTransitionStageLabel::checkpoint_after::<T>(&stage);
+            self.add_stage_after(stage, label, checkpoint)
+        }
+        fn add_loopless_state_checkpoint_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App {
+            let checkpoint = self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)")
+                .checkpoint();
+            let label = StateTransitionStageLabel::checkpoint_before::<T>(&stage);
+            self.add_stage_before(stage, label, checkpoint)
+        }
+        fn add_computed_state<D: StateData, A: StateData, B: StateData>(&mut self, compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>) -> &mut App {
+            self.add_stage_before(
+                CoreStage::Update,
+                StateTransitionStageLabel::from_type::<D>(),
+                StateTransitionStage::computed(compute_from_states(compute))
+            );
+            apply_pending_registrations::<D>(self)
+        }
+        fn add_computed_state_after_stage<D: StateData, A: StateData, B: StateData>(&mut self, stage: impl StageLabel, compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>) -> &mut App {
+            self.add_stage_after(
+                stage,
+                StateTransitionStageLabel::from_type::<D>(),
+                StateTransitionStage::computed(compute_from_states(compute))
+            );
+            apply_pending_registrations::<D>(self)
+        }
+        fn add_reenter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_reenter_system(state, system))
+        }
+        fn set_same_state_policy<T: StateData>(&mut self, policy: SameStatePolicy) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.set_same_state_policy(policy))
+        }
+        fn enable_state_history<T: StateData>(&mut self, capacity: usize) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.enable_history(capacity))
+        }
+        fn allow_state_transition<T: StateData>(&mut self, from: T, to: T) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.allow(from, to))
+        }
+        fn allow_state_transition_any_from<T: StateData>(&mut self, to: T) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.allow_any_from(to))
+        }
+        fn enable_state_transition_tracking<T: StateData>(&mut self) -> &mut App {
+            with_state_stage::<T>(self, |stage| stage.enable_transition_tracking())
+        }
+        #[cfg(feature = "timed")]
+        fn set_state_transition_duration<T: StateData>(&mut self, duration: Duration) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.set_transition_duration(duration))
+        }
+        #[cfg(feature = "timed")]
+        fn add_state_transition_system<T: StateData, Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_transition_system(system))
+        }
+        fn add_readiness_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystem<(), bool, Params>) -> &mut App {
+            let system = IntoSystem::into_system(system);
+            with_state_stage::<T>(self, move |stage| stage.add_readiness_system(state, system))
+        }
+        fn enable_state_coverage_check<T: LooplessStates>(&mut self) -> &mut App {
+            with_state_stage::<T>(self, |stage| stage.enable_coverage_check())
+        }
+        fn add_enter_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut App {
+            for state in T::variants().into_iter().filter(filter) {
+                self.add_enter_system(state, system.clone());
+            }
+            self
+        }
+        fn add_exit_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut App {
+            for state in T::variants().into_iter().filter(filter) {
+                self.add_exit_system(state, system.clone());
+            }
+            self
+        }
+        fn add_enter_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_enter_system_matching(predicate, system))
+        }
+        fn add_exit_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_exit_system_matching(predicate, system))
+        }
+        fn add_scoped_resource<T: StateData, R: Resource>(&mut self, state: T, init: impl Fn() -> R + Send + Sync + 'static) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_scoped_resource(state, init))
+        }
+        #[cfg(feature = "state-scoped")]
+        fn enable_state_scoped_entities<T: StateData>(&mut self) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.enable_state_scoped_entities())
+        }
+        fn add_enter_child_stage<T: StateData>(&mut self, state: T) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_enter_child_stage(state))
+        }
+        fn add_enter_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_enter_substage_system(state, substage_i, system))
+        }
+        fn add_enter_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_enter_substage_system_set(state, substage_i, system_set))
+        }
+        fn add_exit_child_stage<T: StateData>(&mut self, state: T) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_exit_child_stage(state))
+        }
+        fn add_exit_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_exit_substage_system(state, substage_i, system))
+        }
+        fn add_exit_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_exit_substage_system_set(state, substage_i, system_set))
+        }
+        fn add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_enter_system(state, system))
+        }
+        fn add_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let system = system.into_descriptor();
+            with_state_stage::<T>(self, move |stage| stage.add_exit_system(state, system))
+        }
+        fn add_enter_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_enter_system_set(state, system_set))
+        }
+        fn add_exit_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.add_exit_system_set(state, system_set))
+        }
+        fn set_enter_stage<T: StateData>(&mut self, state: T, enter_stage: impl Stage) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.set_enter_stage(state, enter_stage))
+        }
+        fn set_exit_stage<T: StateData>(&mut self, state: T, exit_stage: impl Stage) -> &mut App {
+            with_state_stage::<T>(self, move |stage| stage.set_exit_stage(state, exit_stage))
+        }
+        fn try_get_loopless_state_stage_mut<T: StateData>(&mut self) -> Result<&mut StateTransitionStage<T>, StateStageError> {
+            self.schedule.try_get_loopless_state_stage_mut()
+        }
+        fn try_add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut App, StateStageError> {
+            self.schedule.try_add_enter_system(state, system)?;
+            Ok(self)
+        }
+        fn try_add_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut App, StateStageError> {
+            self.schedule.try_add_exit_system(state, system)?;
+            Ok(self)
+        }
+        fn try_add_enter_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut App, StateStageError> {
+            self.schedule.try_add_enter_system_set(state, system_set)?;
+            Ok(self)
+        }
+        fn try_add_exit_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut App, StateStageError> {
+            self.schedule.try_add_exit_system_set(state, system_set)?;
+            Ok(self)
+        }
+        fn try_set_enter_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut App, StateStageError> {
+            self.schedule.try_set_enter_stage(state, stage)?;
+            Ok(self)
+        }
+        fn try_set_exit_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut App, StateStageError> {
+            self.schedule.try_set_exit_stage(state, stage)?;
+            Ok(self)
+        }
+    }
+
+    type StateRegistration<T> = Box<dyn FnOnce(&mut StateTransitionStage<T>)>;
+
+    /// State registrations made before the `StateTransitionStage` was added
+    struct PendingStateRegistrations<T: StateData>(Vec<StateRegistration<T>>);
+
+    /// Apply `f` to the `StateTransitionStage` of the state type, or buffer it until the stage is added
+    fn with_state_stage<T: StateData>(app: &mut App, f: impl FnOnce(&mut StateTransitionStage<T>) + 'static) -> &mut App {
+        if let Some(stage) = app.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>()) {
+            f(stage);
+        } else if let Some(mut pending) = app.world.get_non_send_resource_mut::<PendingStateRegistrations<T>>() {
+            pending.0.push(Box::new(f));
+        } else {
+            app.world.insert_non_send_resource(PendingStateRegistrations::<T>(vec![Box::new(f)]));
+            app.add_startup_system(warn_pending_registrations::<T>);
+        }
+        app
+    }
+
+    /// Startup system to report registrations for a state type that was never added
+    fn warn_pending_registrations<T: StateData>(pending: Option<NonSend<PendingStateRegistrations<T>>>) {
+        if let Some(pending) = pending {
+            warn!(
+                "{} registration(s) for state type {} were ignored, because its StateTransitionStage was never added",
+                pending.0.len(),
+                std::any::type_name::<T>(),
+            );
+        }
+    }
+
+    /// Apply any buffered registrations, after adding the `StateTransitionStage`
+    fn apply_pending_registrations<T: StateData>(app: &mut App) -> &mut App {
+        if let Some(pending) = app.world.remove_non_send_resource::<PendingStateRegistrations<T>>() {
+            let stage = app.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            for f in pending.0 {
+                f(stage);
+            }
+        }
+        app
+    }
+}
+
+/// Extensions to Bevy Schedule
+pub mod schedule {
+    use bevy_ecs::schedule::{StageLabel, Stage, StateData, IntoSystemDescriptor, SystemSet, Schedule};
+    use bevy_ecs::system::{IntoSystem, Resource};
+    #[cfg(feature = "timed")]
+    use bevy_utils::Duration;
+
+    use super::coverage::LooplessStates;
+    use super::{CurrentState, SameStatePolicy, StateStageError, StateTransitionStage, StateTransitionStageLabel, compute_from_states};
+
+    /// Extension trait with the methods to add to Bevy's `Schedule`
+    ///
+    /// Unlike the `App` methods, these do not buffer registrations: the
+    /// `StateTransitionStage` must be added before configuring it.
+    pub trait ScheduleLooplessStateExt {
+        /// Add a `StateTransitionStage` after the specified stage
+        fn add_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, init: T) -> &mut Schedule;
         /// Add a `StateTransitionStage` before the specified stage
         fn add_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, init: T) -> &mut Schedule;
+        /// Add a `StateTransitionStage` with a custom label, after the specified stage
+        ///
+        /// Use [`get_labeled_loopless_state_stage_mut`](Self::get_labeled_loopless_state_stage_mut)
+        /// with the same label to configure it, or the `add_labeled_enter_system`/`add_labeled_exit_system`
+        /// methods; the other helper methods (and buffered registrations) only work with the default label.
+        ///
+        /// The stages are not independent instances: all stages for the same state type use
+        /// the same `CurrentState<T>` and `NextState<T>` resources. To have separate state values,
+        /// the stages must run on different `World`s (like in a sub-app), or use different state types.
+        fn add_labeled_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut Schedule;
+        /// Add a `StateTransitionStage` with a custom label, before the specified stage
+        ///
+        /// See [`add_labeled_loopless_state_after_stage`](Self::add_labeled_loopless_state_after_stage).
+        fn add_labeled_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut Schedule;
+        /// Get the `StateTransitionStage` with the given label
+        ///
+        /// Panics if it does not exist, or is for a different state type.
+        fn get_labeled_loopless_state_stage_mut<T: StateData>(&mut self, label: impl StageLabel) -> &mut StateTransitionStage<T>;
+        /// Add an enter system to the `StateTransitionStage` with the given label
+        ///
+        /// Panics if it does not exist, or is for a different state type.
+        fn add_labeled_enter_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add an exit system to the `StateTransitionStage` with the given label
+        ///
+        /// Panics if it does not exist, or is for a different state type.
+        fn add_labeled_exit_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a `StateTransitionStage` for a sub-state of another state type
+        ///
+        /// The sub-state only exists while the parent state has the value `parent`.
+        /// The stage is added right after the parent's `StateTransitionStage`.
+        /// The sub-state is set up/torn down during the parent's transitions
+        /// (see `StateTransitionStage::add_sub_state`).
+        ///
+        /// Requires the parent stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_loopless_sub_state<P: StateData, T: StateData>(&mut self, parent: P, init: T) -> &mut Schedule;
+        /// Add a checkpoint for an existing state type, after the specified stage
+        ///
+        /// The checkpoint is another stage that checks for and performs state transitions,
+        /// sharing the enter/exit stages with the main `StateTransitionStage`. This way,
+        /// `NextState` can be applied at multiple points in the frame.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_loopless_state_checkpoint_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule;
+        /// Add a checkpoint for an existing state type, before the specified stage
+        ///
+        /// See [`add_loopless_state_checkpoint_after_stage`](Self::add_loopless_state_checkpoint_after_stage).
+        fn add_loopless_state_checkpoint_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule;
+        /// Add a `StateTransitionStage` for a computed state, after the specified stage
+        ///
+        /// The value of the state is derived from two other state types, using the
+        /// provided function. It is recomputed every time the stage runs. If either
+        /// of the source states does not exist, or the function returns `None`,
+        /// the computed state will not exist either.
+        fn add_computed_state_after_stage<D: StateData, A: StateData, B: StateData>(&mut self, stage: impl StageLabel, compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>) -> &mut Schedule;
+        /// Add a re-enter system for the given state
+        ///
+        /// Only used if the `SameStatePolicy` for the state type is `Reenter`.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_reenter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Set what should happen when the requested next state is the same as the current state
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn set_same_state_policy<T: StateData>(&mut self, policy: SameStatePolicy) -> &mut Schedule;
+        /// Keep a record of previous states in a `StateHistory<T>` resource
+        ///
+        /// Needed for `GoBack<T>` to work.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn enable_state_history<T: StateData>(&mut self, capacity: usize) -> &mut Schedule;
+        /// Allow transitions of the given state type from one specific state to another
+        ///
+        /// Once any are declared, other transitions are rejected. See the [`allowed`](super::allowed) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn allow_state_transition<T: StateData>(&mut self, from: T, to: T) -> &mut Schedule;
+        /// Allow transitions from any state to the given state
+        ///
+        /// Once any are declared, other transitions are rejected. See the [`allowed`](super::allowed) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn allow_state_transition_any_from<T: StateData>(&mut self, to: T) -> &mut Schedule;
+        /// Count the transitions of the given state type, to show them in its `StateGraph`
+        ///
+        /// See the [`graph`](super::graph) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn enable_state_transition_tracking<T: StateData>(&mut self) -> &mut Schedule;
+        /// Make transitions of the given state type take the given amount of time
+        ///
+        /// While in progress, a `Transitioning<T>` resource exists. See the [`timed`](super::timed) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        #[cfg(feature = "timed")]
+        fn set_state_transition_duration<T: StateData>(&mut self, duration: Duration) -> &mut Schedule;
+        /// Add a system to run once per frame while a timed transition of the given state type is in progress
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        #[cfg(feature = "timed")]
+        fn add_state_transition_system<T: StateData, Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a system that must return `true` before entering the given state
+        ///
+        /// While waiting, a `PendingTransition<T>` resource exists. See the [`readiness`](super::readiness) module.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_readiness_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystem<(), bool, Params>) -> &mut Schedule;
+        /// Check which states of the given type have no enter/exit systems, when its stage first runs
+        ///
+        /// Missing ones are logged as a warning. See [`LooplessStates`].
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn enable_state_coverage_check<T: LooplessStates>(&mut self) -> &mut Schedule;
+        /// Add an enter system for all the states that match the filter
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut Schedule;
+        /// Add an exit system for all the states that match the filter
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut Schedule;
+        /// Add a system to run when entering any state that matches the predicate
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a system to run when exiting any state that matches the predicate
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a resource that should only exist while in the given state
+        ///
+        /// It is inserted when entering the state and removed when exiting it.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_scoped_resource<T: StateData, R: Resource>(&mut self, state: T, init: impl Fn() -> R + Send + Sync + 'static) -> &mut Schedule;
+        /// Despawn entities with a `StateScoped<T>` component when exiting their state
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        #[cfg(feature = "state-scoped")]
+        fn enable_state_scoped_entities<T: StateData>(&mut self) -> &mut Schedule;
+        /// Add a new enter sub-stage for the given state
+        ///
+        /// Sub-stages run in order, so `Commands` from systems in one sub-stage
+        /// are applied before the next sub-stage runs. Use `add_enter_substage_system`
+        /// to add systems to it.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_child_stage<T: StateData>(&mut self, state: T) -> &mut Schedule;
+        /// Add a system to the given enter sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a system set to the given enter sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_enter_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut Schedule;
+        /// Add a new exit sub-stage for the given state
+        ///
+        /// Sub-stages run in order, so `Commands` from systems in one sub-stage
+        /// are applied before the next sub-stage runs. Use `add_exit_substage_system`
+        /// to add systems to it.
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_child_stage<T: StateData>(&mut self, state: T) -> &mut Schedule;
+        /// Add a system to the given exit sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a system set to the given exit sub-stage of the given state
+        ///
+        /// `substage_i` is the index of the sub-stage (`0` if you have not added
+        /// any additional sub-stages).
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_exit_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut Schedule;
         /// Add an enter system for the given state
         ///
         /// Requires the stage to be labeled with a `StateTransitionStageLabel`
@@ -427,6 +2194,23 @@ pub mod schedule {
         ///
         /// Cannot be used together with `add_enter_system`.
         fn set_exit_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> &mut Schedule;
+        /// Get the `StateTransitionStage` for the given state type
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn try_get_loopless_state_stage_mut<T: StateData>(&mut self) -> Result<&mut StateTransitionStage<T>, StateStageError>;
+        /// Like `add_enter_system`, but returns an error if it is not possible
+        fn try_add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut Schedule, StateStageError>;
+        /// Like `add_exit_system`, but returns an error if it is not possible
+        fn try_add_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut Schedule, StateStageError>;
+        /// Like `add_enter_system_set`, but returns an error if it is not possible
+        fn try_add_enter_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut Schedule, StateStageError>;
+        /// Like `add_exit_system_set`, but returns an error if it is not possible
+        fn try_add_exit_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut Schedule, StateStageError>;
+        /// Like `set_enter_stage`, but returns an error if it is not possible
+        fn try_set_enter_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut Schedule, StateStageError>;
+        /// Like `set_exit_stage`, but returns an error if it is not possible
+        fn try_set_exit_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut Schedule, StateStageError>;
     }
 
     impl ScheduleLooplessStateExt for Schedule {
@@ -444,6 +2228,193 @@ pub mod schedule {
                 StateTransitionStage::new(init)
             )
         }
+        fn add_labeled_loopless_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut Schedule {
+            self.add_stage_after(stage, label, StateTransitionStage::new(init))
+        }
+        fn add_labeled_loopless_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel, label: impl StageLabel, init: T) -> &mut Schedule {
+            self.add_stage_before(stage, label, StateTransitionStage::new(init))
+        }
+        fn get_labeled_loopless_state_stage_mut<T: StateData>(&mut self, label: impl StageLabel) -> &mut StateTransitionStage<T> {
+            self.get_stage_mut::<StateTransitionStage<T>>(label)
+                .expect("State Transition Stage not found")
+        }
+        fn add_labeled_enter_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            self.get_labeled_loopless_state_stage_mut::<T>(label)
+                .add_enter_system(state, system);
+            self
+        }
+        fn add_labeled_exit_system<T: StateData, Params>(&mut self, label: impl StageLabel, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            self.get_labeled_loopless_state_stage_mut::<T>(label)
+                .add_exit_system(state, system);
+            self
+        }
+        fn add_loopless_sub_state<P: StateData, T: StateData>(&mut self, parent: P, init: T) -> &mut Schedule {
+            let mut stage = StateTransitionStage::new(init);
+            self.get_stage_mut::<StateTransitionStage<P>>(StateTransitionStageLabel::from_type::<P>())
+                .expect("State Transition Stage not found (assuming auto-added label)")
+                .add_sub_state(parent, &mut stage);
+            self.add_stage_after(
+                StateTransitionStageLabel::from_type::<P>(),
+                StateTransitionStageLabel::from_type::<T>(),
+                stage
+            )
+        }
+        fn add_loopless_state_checkpoint_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule {
+            let checkpoint = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)")
+                .checkpoint();
+            let label = StateTransitionStageLabel::checkpoint_after::<T>(&stage);
+            self.add_stage_after(stage, label, checkpoint)
+        }
+        fn add_loopless_state_checkpoint_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule {
+            let checkpoint = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)")
+                .checkpoint();
+            let label = StateTransitionStageLabel::checkpoint_before::<T>(&stage);
+            self.add_stage_before(stage, label, checkpoint)
+        }
+        fn add_computed_state_after_stage<D: StateData, A: StateData, B: StateData>(&mut self, stage: impl StageLabel, compute: fn(&CurrentState<A>, &CurrentState<B>) -> Option<D>) -> &mut Schedule {
+            self.add_stage_after(
+                stage,
+                StateTransitionStageLabel::from_type::<D>(),
+                StateTransitionStage::computed(compute_from_states(compute))
+            )
+        }
+        fn add_reenter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_reenter_system(state, system);
+            self
+        }
+        fn set_same_state_policy<T: StateData>(&mut self, policy: SameStatePolicy) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.set_same_state_policy(policy);
+            self
+        }
+        fn enable_state_history<T: StateData>(&mut self, capacity: usize) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.enable_history(capacity);
+            self
+        }
+        fn allow_state_transition<T: StateData>(&mut self, from: T, to: T) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.allow(from, to);
+            self
+        }
+        fn allow_state_transition_any_from<T: StateData>(&mut self, to: T) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.allow_any_from(to);
+            self
+        }
+        fn enable_state_transition_tracking<T: StateData>(&mut self) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.enable_transition_tracking();
+            self
+        }
+        #[cfg(feature = "timed")]
+        fn set_state_transition_duration<T: StateData>(&mut self, duration: Duration) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.set_transition_duration(duration);
+            self
+        }
+        #[cfg(feature = "timed")]
+        fn add_state_transition_system<T: StateData, Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_transition_system(system);
+            self
+        }
+        fn add_readiness_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystem<(), bool, Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_readiness_system(state, system);
+            self
+        }
+        fn enable_state_coverage_check<T: LooplessStates>(&mut self) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.enable_coverage_check();
+            self
+        }
+        fn add_enter_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_system_for_all(filter, system);
+            self
+        }
+        fn add_exit_system_for_all<T: LooplessStates, Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_system_for_all(filter, system);
+            self
+        }
+        fn add_enter_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_system_matching(predicate, system);
+            self
+        }
+        fn add_exit_system_matching<T: StateData, Params>(&mut self, predicate: impl Fn(&T) -> bool + Send + Sync + 'static, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_system_matching(predicate, system);
+            self
+        }
+        fn add_scoped_resource<T: StateData, R: Resource>(&mut self, state: T, init: impl Fn() -> R + Send + Sync + 'static) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_scoped_resource(state, init);
+            self
+        }
+        #[cfg(feature = "state-scoped")]
+        fn enable_state_scoped_entities<T: StateData>(&mut self) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.enable_state_scoped_entities();
+            self
+        }
+        fn add_enter_child_stage<T: StateData>(&mut self, state: T) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_child_stage(state);
+            self
+        }
+        fn add_enter_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_substage_system(state, substage_i, system);
+            self
+        }
+        fn add_enter_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_substage_system_set(state, substage_i, system_set);
+            self
+        }
+        fn add_exit_child_stage<T: StateData>(&mut self, state: T) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_child_stage(state);
+            self
+        }
+        fn add_exit_substage_system<T: StateData, Params>(&mut self, state: T, substage_i: usize, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_substage_system(state, substage_i, system);
+            self
+        }
+        fn add_exit_substage_system_set<T: StateData>(&mut self, state: T, substage_i: usize, system_set: SystemSet) -> &mut Schedule {
+            let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .expect("State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_substage_system_set(state, substage_i, system_set);
+            self
+        }
         fn add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
             let stage = self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
                 .expect("State Transition Stage not found (assuming auto-added label)");
@@ -480,5 +2451,342 @@ pub mod schedule {
             stage.set_exit_stage(state, exit_stage);
             self
         }
+        fn try_get_loopless_state_stage_mut<T: StateData>(&mut self) -> Result<&mut StateTransitionStage<T>, StateStageError> {
+            self.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+                .ok_or(StateStageError::StageNotFound {
+                    state_type: std::any::type_name::<T>(),
+                })
+        }
+        fn try_add_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut Schedule, StateStageError> {
+            let stage = self.try_get_loopless_state_stage_mut::<T>()?;
+            stage.check_enter_system_stage(&state)?;
+            stage.add_enter_system(state, system);
+            Ok(self)
+        }
+        fn try_add_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Result<&mut Schedule, StateStageError> {
+            let stage = self.try_get_loopless_state_stage_mut::<T>()?;
+            stage.check_exit_system_stage(&state)?;
+            stage.add_exit_system(state, system);
+            Ok(self)
+        }
+        fn try_add_enter_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut Schedule, StateStageError> {
+            let stage = self.try_get_loopless_state_stage_mut::<T>()?;
+            stage.check_enter_system_stage(&state)?;
+            stage.add_enter_system_set(state, system_set);
+            Ok(self)
+        }
+        fn try_add_exit_system_set<T: StateData>(&mut self, state: T, system_set: SystemSet) -> Result<&mut Schedule, StateStageError> {
+            let stage = self.try_get_loopless_state_stage_mut::<T>()?;
+            stage.check_exit_system_stage(&state)?;
+            stage.add_exit_system_set(state, system_set);
+            Ok(self)
+        }
+        fn try_set_enter_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut Schedule, StateStageError> {
+            self.try_get_loopless_state_stage_mut::<T>()?
+                .set_enter_stage(state, stage);
+            Ok(self)
+        }
+        fn try_set_exit_stage<T: StateData>(&mut self, state: T, stage: impl Stage) -> Result<&mut Schedule, StateStageError> {
+            self.try_get_loopless_state_stage_mut::<T>()?
+                .set_exit_stage(state, stage);
+            Ok(self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::event::Events;
+    use bevy_ecs::schedule::Stage;
+    use bevy_ecs::system::{Commands, Res, ResMut, Resource};
+    use bevy_ecs::world::World;
+
+    use super::allowed::StateTransitionRejected;
+    use super::readiness::PendingTransition;
+    use super::{CurrentState, GoBack, NextState, SameStatePolicy, StateHistory, StateQueue, StateTransitionLoop, StateTransitionStage};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum MyState {
+        A,
+        B,
+        C,
+    }
+
+    #[derive(Resource, Default)]
+    struct Log(Vec<&'static str>);
+
+    fn log(entry: &'static str) -> impl Fn(ResMut<Log>) {
+        move |mut log: ResMut<Log>| log.0.push(entry)
+    }
+
+    fn request(state: MyState) -> impl Fn(Commands) {
+        move |mut commands: Commands| commands.insert_resource(NextState(state))
+    }
+
+    /// A stage whose enter systems keep requesting the other state
+    fn looping_stage() -> StateTransitionStage<MyState> {
+        StateTransitionStage::new(MyState::A)
+            .with_max_transitions(3)
+            .with_enter_system(MyState::A, request(MyState::B))
+            .with_enter_system(MyState::B, request(MyState::A))
+    }
+
+    /// Run a stage in state `A` with the given policy, then request `A` again
+    fn request_same_state(stage: StateTransitionStage<MyState>) -> Vec<&'static str> {
+        let mut stage = stage
+            .with_enter_system(MyState::A, log("enter A"))
+            .with_exit_system(MyState::A, log("exit A"))
+            .with_enter_system(MyState::B, log("enter B"));
+        let mut world = World::new();
+        world.init_resource::<Log>();
+        stage.run(&mut world);
+        world.resource_mut::<Log>().0.clear();
+        world.insert_resource(NextState(MyState::A));
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::A);
+        assert!(!world.contains_resource::<NextState<MyState>>());
+        world.remove_resource::<Log>().unwrap().0
+    }
+
+    #[test]
+    fn same_state_restart() {
+        let stage = StateTransitionStage::new(MyState::A)
+            .with_same_state_policy(SameStatePolicy::Restart);
+        assert_eq!(request_same_state(stage), ["exit A", "enter A"]);
+    }
+
+    #[test]
+    fn same_state_ignore() {
+        let stage = StateTransitionStage::new(MyState::A)
+            .with_same_state_policy(SameStatePolicy::Ignore);
+        assert!(request_same_state(stage).is_empty());
+    }
+
+    #[test]
+    fn same_state_reenter() {
+        let stage = StateTransitionStage::new(MyState::A)
+            .with_same_state_policy(SameStatePolicy::Reenter)
+            .with_reenter_system(MyState::A, log("reenter A"));
+        assert_eq!(request_same_state(stage), ["reenter A"]);
+    }
+
+    #[test]
+    fn same_state_reenter_without_stage() {
+        let stage = StateTransitionStage::new(MyState::A)
+            .with_same_state_policy(SameStatePolicy::Reenter);
+        assert!(request_same_state(stage).is_empty());
+    }
+
+    #[test]
+    fn go_back_with_empty_history_does_not_affect_queue() {
+        let mut stage = StateTransitionStage::new(MyState::A).with_history(4);
+        let mut world = World::new();
+        stage.run(&mut world);
+        world.insert_resource(GoBack::<MyState>::default());
+        world.insert_resource(StateQueue::new().with_state(MyState::B));
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::B);
+        let history = world.resource::<StateHistory<MyState>>();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.previous(), Some(&MyState::A));
+    }
+
+    #[test]
+    fn go_back() {
+        let mut stage = StateTransitionStage::new(MyState::A).with_history(4);
+        let mut world = World::new();
+        stage.run(&mut world);
+        world.insert_resource(NextState(MyState::B));
+        stage.run(&mut world);
+        world.insert_resource(GoBack::<MyState>::default());
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::A);
+        assert!(world.resource::<StateHistory<MyState>>().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn restore_skips_allowed_check() {
+        use super::snapshot::{register_serializable_state, restore_states, snapshot_states};
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+        enum Saved {
+            A,
+            B,
+        }
+
+        let mut stage = StateTransitionStage::new(Saved::A).with_allowed(Saved::A, Saved::B);
+        let mut world = World::new();
+        register_serializable_state::<Saved>(&mut world, "saved");
+        stage.run(&mut world);
+        let snapshot = snapshot_states(&world).unwrap();
+        world.insert_resource(NextState(Saved::B));
+        stage.run(&mut world);
+        restore_states(&mut world, &snapshot).unwrap();
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<Saved>>().0, Saved::A);
+    }
+
+    #[test]
+    #[should_panic(expected = "State transition loop detected")]
+    fn transition_loop_panics() {
+        let mut world = World::new();
+        looping_stage().run(&mut world);
+    }
+
+    #[test]
+    fn transition_loop_event() {
+        let mut world = World::new();
+        world.init_resource::<Events<StateTransitionLoop<MyState>>>();
+        looping_stage().run(&mut world);
+        let events = world.resource::<Events<StateTransitionLoop<MyState>>>();
+        let loops: Vec<_> = events.get_reader().iter(events).map(|event| event.states.clone()).collect();
+        assert_eq!(loops, [vec![MyState::A, MyState::B, MyState::A, MyState::B, MyState::A]]);
+        // the last request is discarded, after 3 transitions
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::B);
+        assert!(!world.contains_resource::<NextState<MyState>>());
+    }
+
+    #[derive(Resource, Default)]
+    struct Ready(bool);
+
+    /// A stage in state `A`, where entering `B` waits for the `Ready` resource
+    fn readiness_stage(world: &mut World) -> StateTransitionStage<MyState> {
+        let mut stage = StateTransitionStage::new(MyState::A)
+            .with_readiness_system(MyState::B, |ready: Res<Ready>| ready.0);
+        world.init_resource::<Ready>();
+        stage.run(world);
+        stage
+    }
+
+    #[test]
+    fn readiness_pending_then_ready() {
+        let mut world = World::new();
+        let mut stage = readiness_stage(&mut world);
+        world.insert_resource(NextState(MyState::B));
+        stage.run(&mut world);
+        world.clear_trackers();
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::A);
+        let pending = world.resource::<PendingTransition<MyState>>();
+        assert_eq!((pending.from, pending.to), (MyState::A, MyState::B));
+        // left untouched while the same request is pending
+        assert!(!world.is_resource_changed::<PendingTransition<MyState>>());
+
+        world.resource_mut::<Ready>().0 = true;
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::B);
+        assert!(!world.contains_resource::<PendingTransition<MyState>>());
+    }
+
+    #[test]
+    fn readiness_pending_replaced() {
+        let mut world = World::new();
+        let mut stage = readiness_stage(&mut world);
+        world.insert_resource(NextState(MyState::B));
+        stage.run(&mut world);
+        assert!(world.contains_resource::<PendingTransition<MyState>>());
+
+        world.insert_resource(NextState(MyState::C));
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::C);
+        assert!(!world.contains_resource::<PendingTransition<MyState>>());
+        // the replaced request is not performed later
+        world.resource_mut::<Ready>().0 = true;
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::C);
+    }
+
+    #[test]
+    fn disallowed_transition_rejected() {
+        let mut stage = StateTransitionStage::new(MyState::A)
+            .with_allowed(MyState::A, MyState::B)
+            .with_enter_system(MyState::C, log("enter C"));
+        let mut world = World::new();
+        world.init_resource::<Log>();
+        world.init_resource::<Events<StateTransitionRejected<MyState>>>();
+        stage.run(&mut world);
+        world.insert_resource(NextState(MyState::C));
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::A);
+        assert!(!world.contains_resource::<NextState<MyState>>());
+        assert!(world.resource::<Log>().0.is_empty());
+        let events = world.resource::<Events<StateTransitionRejected<MyState>>>();
+        let rejected: Vec<_> = events.get_reader().iter(events).map(|event| (event.from, event.to)).collect();
+        assert_eq!(rejected, [(MyState::A, MyState::C)]);
+
+        // allowed transitions are still performed
+        world.insert_resource(NextState(MyState::B));
+        stage.run(&mut world);
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, MyState::B);
+    }
+
+    /// Run a frame: the Bevy state bridge stages around `stage`, then the Bevy state driver
+    #[cfg(feature = "bevy-compat")]
+    fn bridged_frame(mut stage: StateTransitionStage<MyState>) -> impl FnMut(&mut World) {
+        use bevy_ecs::schedule::{State, SystemStage};
+        use super::bevy_compat::{BevyStateBridgeStage, BevyStateForwardStage};
+
+        let mut bridge = BevyStateBridgeStage::<MyState>::new();
+        let mut forward = BevyStateForwardStage::<MyState>::new();
+        let mut driver = SystemStage::parallel().with_system_set(State::<MyState>::get_driver());
+        move |world: &mut World| {
+            bridge.run(world);
+            stage.run(world);
+            forward.run(world);
+            driver.run(world);
+        }
+    }
+
+    #[cfg(feature = "bevy-compat")]
+    fn assert_bridged(world: &World, state: MyState) {
+        assert_eq!(world.resource::<CurrentState<MyState>>().0, state);
+        assert_eq!(*world.resource::<bevy_ecs::schedule::State<MyState>>().current(), state);
+    }
+
+    #[test]
+    #[cfg(feature = "bevy-compat")]
+    fn bevy_state_bridge_queue_and_go_back() {
+        let mut frame = bridged_frame(StateTransitionStage::new(MyState::A).with_history(4));
+        let mut world = World::new();
+        world.insert_resource(bevy_ecs::schedule::State::new(MyState::A));
+        frame(&mut world);
+        world.insert_resource(StateQueue::new().with_state(MyState::B).with_state(MyState::C));
+        frame(&mut world);
+        assert_bridged(&world, MyState::C);
+        frame(&mut world);
+        assert_bridged(&world, MyState::C);
+        world.insert_resource(GoBack::<MyState>::default());
+        frame(&mut world);
+        assert_bridged(&world, MyState::B);
+        frame(&mut world);
+        assert_bridged(&world, MyState::B);
+    }
+
+    #[test]
+    #[cfg(feature = "bevy-compat")]
+    fn bevy_state_bridge_both_directions() {
+        let stage = StateTransitionStage::new(MyState::A)
+            .with_allowed(MyState::A, MyState::B)
+            .with_allowed(MyState::B, MyState::A);
+        let mut frame = bridged_frame(stage);
+        let mut world = World::new();
+        world.insert_resource(bevy_ecs::schedule::State::new(MyState::A));
+        frame(&mut world);
+
+        // rejected requests are not forwarded
+        world.insert_resource(NextState(MyState::C));
+        frame(&mut world);
+        assert_bridged(&world, MyState::A);
+
+        world.insert_resource(NextState(MyState::B));
+        frame(&mut world);
+        assert_bridged(&world, MyState::B);
+
+        // Bevy state changes are mirrored
+        world.resource_mut::<bevy_ecs::schedule::State<MyState>>().set(MyState::A).unwrap();
+        frame(&mut world);
+        frame(&mut world);
+        assert_bridged(&world, MyState::A);
     }
 }
@@ -0,0 +1,119 @@
+//! Declaring which state transitions are allowed
+//!
+//! By default, any state can transition to any other state. Once you declare
+//! any allowed transitions on a [`StateTransitionStage`] (using [`allow`](StateTransitionStage::allow)
+//! or [`allow_any_from`](StateTransitionStage::allow_any_from)), only those are
+//! performed. Requests for any other transitions are discarded, logged as an
+//! error, and reported via a [`StateTransitionRejected`] event.
+//!
+//! Transitions to the same state (see [`SameStatePolicy`](super::SameStatePolicy))
+//! are always allowed. Computed states are not checked, and neither are the
+//! transitions requested by restoring a state snapshot (see the `snapshot` module),
+//! since the saved state might not be reachable directly from the current one.
+
+use bevy_ecs::event::Events;
+use bevy_ecs::schedule::StateData;
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_utils::tracing::error;
+
+use super::{StateTransitionStage, StateTransitions};
+
+/// Event sent when a requested state transition is not allowed
+///
+/// The request is discarded.
+///
+/// The event is only sent if you add it to your app (`app.add_event::<StateTransitionRejected<T>>()`).
+#[derive(Debug, Clone)]
+pub struct StateTransitionRejected<T> {
+    /// The current state
+    pub from: T,
+    /// The requested state
+    pub to: T,
+}
+
+/// Inserted along with a [`NextState`](super::NextState), to skip the allowed transitions check for it
+#[derive(Resource)]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub(crate) struct UncheckedRequest<T>(pub(crate) T);
+
+impl<T: StateData> StateTransitionStage<T> {
+    /// Allow transitions from one specific state to another
+    pub fn allow(&mut self, from: T, to: T) {
+        self.transitions().allowed.insert((from, to));
+    }
+
+    /// Allow transitions from any state to the given state
+    pub fn allow_any_from(&mut self, to: T) {
+        self.transitions().allowed_any_from.insert(to);
+    }
+
+    /// Builder version of `allow`
+    pub fn with_allowed(mut self, from: T, to: T) -> Self {
+        self.allow(from, to);
+        self
+    }
+
+    /// Builder version of `allow_any_from`
+    pub fn with_allowed_any_from(mut self, to: T) -> Self {
+        self.allow_any_from(to);
+        self
+    }
+
+    /// Would a transition between the given states be performed?
+    pub fn is_transition_allowed(&self, from: &T, to: &T) -> bool {
+        self.transitions().is_transition_allowed(from, to)
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    /// Have any allowed transitions been declared?
+    pub(super) fn has_allowed_transitions(&self) -> bool {
+        !self.allowed.is_empty() || !self.allowed_any_from.is_empty()
+    }
+
+    /// Would a transition between the given states be performed?
+    pub(super) fn is_transition_allowed(&self, from: &T, to: &T) -> bool {
+        if from == to || self.compute.is_some() || !self.has_allowed_transitions() {
+            return true;
+        }
+        self.allowed_any_from.contains(to)
+            || self.allowed.contains(&(from.clone(), to.clone()))
+    }
+
+    /// Check if a transition is allowed; if not, report it
+    pub(super) fn check_allowed(&self, world: &mut World, from: &T, to: &T) -> bool {
+        if self.is_transition_allowed(from, to) {
+            return true;
+        }
+        error!(
+            "State transition {:?} -> {:?} of state type {} is not allowed; discarding the request",
+            from,
+            to,
+            std::any::type_name::<T>(),
+        );
+        if let Some(mut events) = world.get_resource_mut::<Events<StateTransitionRejected<T>>>() {
+            events.send(StateTransitionRejected {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+        false
+    }
+
+    /// Which of the given states can be reached from the starting state, using the allowed transitions
+    pub(super) fn reachable(&self, variants: &[T]) -> Vec<T> {
+        let mut reachable: Vec<T> = self.default.iter().cloned().collect();
+        let mut i = 0;
+        while i < reachable.len() {
+            let from = reachable[i].clone();
+            for to in variants.iter() {
+                if !reachable.contains(to) && self.is_transition_allowed(&from, to) {
+                    reachable.push(to.clone());
+                }
+            }
+            i += 1;
+        }
+        reachable
+    }
+}
@@ -0,0 +1,264 @@
+//! Interoperability with Bevy's legacy `State<T>`
+//!
+//! While migrating, you might have both a Bevy `State<T>` and a loopless
+//! state for the same type. Two stages keep them in sync, so that code using
+//! either API sees the same state:
+//!  - [`BevyStateBridgeStage`], before the `StateTransitionStage` of the state type:
+//!    if the Bevy state has changed, a transition to its new value is requested
+//!    on the loopless side (so that the loopless exit/enter systems run)
+//!  - [`BevyStateForwardStage`], after the `StateTransitionStage`: if the loopless
+//!    [`CurrentState`] has changed, it is forwarded to `State::set`
+//!
+//! Only the resulting [`CurrentState`] is forwarded, so all the ways of changing
+//! the loopless state (like [`StateQueue`](super::StateQueue) or [`GoBack`](super::GoBack))
+//! are reflected in the Bevy state, and requests that are rejected or still waiting
+//! for readiness are not. The last synced value is remembered, to tell which
+//! side has changed. Bevy applies `State::set` when its state driver runs (in
+//! `CoreStage::Update`, with `add_state`), which must happen before the
+//! [`BevyStateBridgeStage`] runs again.
+//!
+//! If both sides have changed, the Bevy state wins: a transition to its value
+//! is requested, replacing any pending `NextState`. If the Bevy state could not
+//! be set (because another Bevy transition was already queued), a warning is
+//! logged, and the loopless state follows once the Bevy transition is done.
+
+use bevy_ecs::schedule::{Stage, StageLabel, State, StateData};
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_utils::tracing::warn;
+
+use std::marker::PhantomData;
+
+use super::{CurrentState, NextState};
+
+/// The value of the state type last seen on both sides
+#[derive(Resource)]
+struct LastSynced<T: StateData>(T);
+
+/// Stage that mirrors changes of a Bevy `State<T>` to the loopless state
+///
+/// Runs before the `StateTransitionStage` of the state type.
+/// Does nothing if there is no Bevy `State<T>` resource.
+pub struct BevyStateBridgeStage<T: StateData> {
+    _pd: PhantomData<T>,
+}
+
+impl<T: StateData> BevyStateBridgeStage<T> {
+    /// Create a new bridge stage for the given state type
+    pub fn new() -> Self {
+        Self {
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T: StateData> Default for BevyStateBridgeStage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: StateData> Stage for BevyStateBridgeStage<T> {
+    fn run(&mut self, world: &mut World) {
+        let bevy_current = match world.get_resource::<State<T>>() {
+            Some(bevy_state) => bevy_state.current().clone(),
+            None => return,
+        };
+        if let Some(synced) = world.get_resource::<LastSynced<T>>() {
+            if synced.0 == bevy_current {
+                return;
+            }
+        }
+        world.insert_resource(LastSynced(bevy_current.clone()));
+
+        if let Some(current) = world.get_resource::<CurrentState<T>>() {
+            if current.0 != bevy_current {
+                world.insert_resource(NextState(bevy_current));
+            }
+        }
+    }
+}
+
+/// Stage that forwards changes of the loopless [`CurrentState<T>`] to a Bevy `State<T>`
+///
+/// Runs after the `StateTransitionStage` of the state type.
+/// Does nothing if there is no Bevy `State<T>` resource.
+pub struct BevyStateForwardStage<T: StateData> {
+    _pd: PhantomData<T>,
+}
+
+impl<T: StateData> BevyStateForwardStage<T> {
+    /// Create a new forward stage for the given state type
+    pub fn new() -> Self {
+        Self {
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T: StateData> Default for BevyStateForwardStage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: StateData> Stage for BevyStateForwardStage<T> {
+    fn run(&mut self, world: &mut World) {
+        if !world.contains_resource::<State<T>>() {
+            return;
+        }
+        let current = match world.get_resource::<CurrentState<T>>() {
+            Some(current) => current.0.clone(),
+            None => return,
+        };
+        if let Some(synced) = world.get_resource::<LastSynced<T>>() {
+            if synced.0 == current {
+                return;
+            }
+        }
+        world.insert_resource(LastSynced(current.clone()));
+
+        let mut bevy_state = world.resource_mut::<State<T>>();
+        if *bevy_state.current() == current {
+            return;
+        }
+        if let Err(e) = bevy_state.set(current.clone()) {
+            // the Bevy state wins
+            warn!(
+                "Cannot forward CurrentState({:?}) of state type {} to Bevy State: {}",
+                current,
+                std::any::type_name::<T>(),
+                e,
+            );
+        }
+    }
+}
+
+/// Type used as a Bevy Stage Label for Bevy state bridge stages
+#[derive(Debug, Clone)]
+pub struct BevyStateBridgeStageLabel(&'static str);
+
+impl StageLabel for BevyStateBridgeStageLabel {
+    fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl BevyStateBridgeStageLabel {
+    /// Construct the label for a bridge stage for the state type T
+    pub fn from_type<T: StateData>() -> Self {
+        BevyStateBridgeStageLabel(super::type_label::<BevyStateBridgeStage<T>>())
+    }
+}
+
+/// Type used as a Bevy Stage Label for Bevy state forward stages
+#[derive(Debug, Clone)]
+pub struct BevyStateForwardStageLabel(&'static str);
+
+impl StageLabel for BevyStateForwardStageLabel {
+    fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl BevyStateForwardStageLabel {
+    /// Construct the label for a forward stage for the state type T
+    pub fn from_type<T: StateData>() -> Self {
+        BevyStateForwardStageLabel(super::type_label::<BevyStateForwardStage<T>>())
+    }
+}
+
+/// Extensions to `bevy_app`
+#[cfg(feature = "app")]
+pub mod app {
+    use bevy_ecs::schedule::StateData;
+    use bevy_app::{App, Plugin};
+
+    use std::marker::PhantomData;
+
+    use crate::state::StateTransitionStageLabel;
+
+    use super::{BevyStateBridgeStage, BevyStateBridgeStageLabel, BevyStateForwardStage, BevyStateForwardStageLabel};
+
+    /// Plugin that keeps a Bevy `State<T>` in sync with the loopless `CurrentState<T>`
+    ///
+    /// Add it after the loopless state (`add_loopless_state`); the Bevy state
+    /// is added separately (`add_state`). Same as `add_bevy_state_bridge::<T>()`.
+    pub struct BevyStateBridgePlugin<T: StateData> {
+        _pd: PhantomData<T>,
+    }
+
+    impl<T: StateData> Default for BevyStateBridgePlugin<T> {
+        fn default() -> Self {
+            Self {
+                _pd: PhantomData,
+            }
+        }
+    }
+
+    impl<T: StateData> Plugin for BevyStateBridgePlugin<T> {
+        fn build(&self, app: &mut App) {
+            app.add_bevy_state_bridge::<T>();
+        }
+    }
+
+    /// Extension trait with the methods to add to Bevy's `App`
+    pub trait AppLooplessBevyStateExt {
+        /// Add a `BevyStateBridgeStage` before the `StateTransitionStage` of the state type,
+        /// and a `BevyStateForwardStage` after it
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods). Unlike the configuration
+        /// methods of `AppLooplessStateExt`, this is not buffered: add the state first.
+        fn add_bevy_state_bridge<T: StateData>(&mut self) -> &mut App;
+    }
+
+    impl AppLooplessBevyStateExt for App {
+        fn add_bevy_state_bridge<T: StateData>(&mut self) -> &mut App {
+            self.add_stage_before(
+                StateTransitionStageLabel::from_type::<T>(),
+                BevyStateBridgeStageLabel::from_type::<T>(),
+                BevyStateBridgeStage::<T>::new()
+            );
+            self.add_stage_after(
+                StateTransitionStageLabel::from_type::<T>(),
+                BevyStateForwardStageLabel::from_type::<T>(),
+                BevyStateForwardStage::<T>::new()
+            )
+        }
+    }
+}
+
+/// Extensions to Bevy Schedule
+pub mod schedule {
+    use bevy_ecs::schedule::{Schedule, StateData};
+
+    use crate::state::StateTransitionStageLabel;
+
+    use super::{BevyStateBridgeStage, BevyStateBridgeStageLabel, BevyStateForwardStage, BevyStateForwardStageLabel};
+
+    /// Extension trait with the methods to add to Bevy's `Schedule`
+    pub trait ScheduleLooplessBevyStateExt {
+        /// Add a `BevyStateBridgeStage` before the `StateTransitionStage` of the state type,
+        /// and a `BevyStateForwardStage` after it
+        ///
+        /// Requires the stage to be labeled with a `StateTransitionStageLabel`
+        /// (as done by the `add_loopless_state*` methods).
+        fn add_bevy_state_bridge<T: StateData>(&mut self) -> &mut Schedule;
+    }
+
+    impl ScheduleLooplessBevyStateExt for Schedule {
+        fn add_bevy_state_bridge<T: StateData>(&mut self) -> &mut Schedule {
+            self.add_stage_before(
+                StateTransitionStageLabel::from_type::<T>(),
+                BevyStateBridgeStageLabel::from_type::<T>(),
+                BevyStateBridgeStage::<T>::new()
+            );
+            self.add_stage_after(
+                StateTransitionStageLabel::from_type::<T>(),
+                BevyStateForwardStageLabel::from_type::<T>(),
+                BevyStateForwardStage::<T>::new()
+            )
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Requesting state transitions through `Commands`
+//!
+//! Inserting a [`NextState`] resource directly will silently overwrite any
+//! earlier request in the same frame. The [`StateCommands`] extension trait
+//! offers an alternative, that keeps track of where each request came from
+//! (the source code location of the call, rather than the name of the system:
+//! `Commands` does not know which system it belongs to, and the location is
+//! more precise anyway), and reports conflicting requests:
+//! if a different state has already been requested, and the transition has
+//! not happened yet, a warning is logged, and a [`StateRequestConflict`] event
+//! is sent (if you have added the event type to your app).
+//!
+//! The last request still wins, like with `NextState`.
+
+use std::panic::Location;
+
+use bevy_ecs::event::Events;
+use bevy_ecs::schedule::StateData;
+use bevy_ecs::system::{Command, Commands, Resource};
+use bevy_ecs::world::World;
+use bevy_utils::tracing::warn;
+
+use super::{CurrentState, GoBack, NextState, StateQueue};
+
+/// Tracks where the pending [`NextState`] was requested from
+#[derive(Resource)]
+pub(crate) struct StateRequestOrigin<T> {
+    state: T,
+    origin: &'static Location<'static>,
+}
+
+/// Event sent when conflicting state transitions are requested using [`StateCommands`]
+///
+/// That is, a state was requested while a different state was already pending.
+#[derive(Debug, Clone)]
+pub struct StateRequestConflict<T> {
+    /// The state that was already pending (and is now replaced)
+    pub previous: T,
+    /// Where the previous request came from (`None` if `NextState` was inserted directly)
+    pub previous_origin: Option<&'static Location<'static>>,
+    /// The newly-requested state
+    pub requested: T,
+    /// Where the new request came from
+    pub origin: &'static Location<'static>,
+}
+
+/// Extension trait for requesting state transitions via `Commands`
+pub trait StateCommands {
+    /// Request a transition to the given state
+    ///
+    /// Like inserting a [`NextState`], but detects conflicting requests.
+    #[track_caller]
+    fn set_state<T: StateData>(&mut self, state: T);
+    /// Add a state to the [`StateQueue`] (creating it, if needed)
+    fn push_state<T: StateData>(&mut self, state: T);
+    /// Request a transition to the current state
+    ///
+    /// By default, this exits and re-enters it (see [`SameStatePolicy`](super::SameStatePolicy)).
+    #[track_caller]
+    fn reset_state<T: StateData>(&mut self);
+    /// Request a transition back to the previous state (see [`GoBack`])
+    fn go_back<T: StateData>(&mut self);
+}
+
+impl StateCommands for Commands<'_, '_> {
+    #[track_caller]
+    fn set_state<T: StateData>(&mut self, state: T) {
+        self.add(SetState {
+            state: Some(state),
+            origin: Location::caller(),
+        });
+    }
+
+    fn push_state<T: StateData>(&mut self, state: T) {
+        self.add(PushState { state });
+    }
+
+    #[track_caller]
+    fn reset_state<T: StateData>(&mut self) {
+        self.add(SetState::<T> {
+            state: None,
+            origin: Location::caller(),
+        });
+    }
+
+    fn go_back<T: StateData>(&mut self) {
+        self.insert_resource(GoBack::<T>::new());
+    }
+}
+
+/// Command to request a state transition
+///
+/// (`None` means the current state)
+struct SetState<T> {
+    state: Option<T>,
+    origin: &'static Location<'static>,
+}
+
+impl<T: StateData> Command for SetState<T> {
+    fn write(self, world: &mut World) {
+        let state = if let Some(state) = self.state {
+            state
+        } else if let Some(current) = world.get_resource::<CurrentState<T>>() {
+            current.0.clone()
+        } else {
+            warn!(
+                "Cannot reset state of type {} (requested at {}): it does not exist",
+                std::any::type_name::<T>(),
+                self.origin,
+            );
+            return;
+        };
+
+        if let Some(pending) = world.get_resource::<NextState<T>>() {
+            if pending.0 != state {
+                let previous_origin = world.get_resource::<StateRequestOrigin<T>>()
+                    .filter(|request| request.state == pending.0)
+                    .map(|request| request.origin);
+                let conflict = StateRequestConflict {
+                    previous: pending.0.clone(),
+                    previous_origin,
+                    requested: state.clone(),
+                    origin: self.origin,
+                };
+                report_conflict(world, conflict);
+            }
+        }
+
+        world.insert_resource(StateRequestOrigin {
+            state: state.clone(),
+            origin: self.origin,
+        });
+        world.insert_resource(NextState(state));
+    }
+}
+
+/// Command to add a state to the `StateQueue`
+struct PushState<T> {
+    state: T,
+}
+
+impl<T: StateData> Command for PushState<T> {
+    fn write(self, world: &mut World) {
+        world.get_resource_or_insert_with(StateQueue::<T>::new)
+            .push(self.state);
+    }
+}
+
+fn report_conflict<T: StateData>(world: &mut World, conflict: StateRequestConflict<T>) {
+    warn!(
+        "Conflicting state transition requests for state type {}: {:?} (requested at {}) replaces {:?} (requested at {})",
+        std::any::type_name::<T>(),
+        conflict.requested,
+        conflict.origin,
+        conflict.previous,
+        conflict.previous_origin
+            .map(|origin| origin.to_string())
+            .unwrap_or_else(|| "unknown location".to_owned()),
+    );
+    if let Some(mut events) = world.get_resource_mut::<Events<StateRequestConflict<T>>>() {
+        events.send(conflict);
+    }
+}
@@ -0,0 +1,137 @@
+//! Checking that all the values of a state type are handled
+//!
+//! Implement [`LooplessStates`] for your state type, to list all of its
+//! values. Then, you can enable a check that reports which states have no
+//! enter/exit systems (or cannot be reached, if allowed transitions have been
+//! declared), and register systems for many states at once.
+
+use bevy_ecs::schedule::{IntoSystemDescriptor, StateData};
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_utils::tracing::warn;
+
+use super::{StateTransitionStage, StateTransitions};
+
+/// Trait for state types whose values can be enumerated
+///
+/// For an enum, `variants` should simply return a `Vec` with all of its variants.
+pub trait LooplessStates: StateData {
+    /// All the possible values of the state type
+    fn variants() -> Vec<Self>;
+}
+
+/// Which states are missing enter/exit stages, or cannot be reached
+///
+/// Inserted as a resource when the coverage check runs
+/// (see [`StateTransitionStage::enable_coverage_check`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Resource)]
+pub struct StateCoverageReport<T> {
+    /// The states that have no enter stage
+    pub missing_enter: Vec<T>,
+    /// The states that have no exit stage
+    pub missing_exit: Vec<T>,
+    /// The states that cannot be reached from the starting state, using the allowed transitions
+    ///
+    /// (empty if no allowed transitions have been declared; see the [`allowed`](super::allowed) module)
+    pub unreachable: Vec<T>,
+}
+
+impl<T> StateCoverageReport<T> {
+    /// Do all the states have both enter and exit stages, and can they all be reached?
+    pub fn is_complete(&self) -> bool {
+        self.missing_enter.is_empty() && self.missing_exit.is_empty() && self.unreachable.is_empty()
+    }
+}
+
+impl<T: LooplessStates> StateTransitionStage<T> {
+    /// Check which states are missing enter/exit stages, or cannot be reached
+    pub fn coverage_report(&self) -> StateCoverageReport<T> {
+        self.transitions().coverage_report(T::variants())
+    }
+
+    /// Check which states are missing enter/exit stages, when the stage first runs
+    ///
+    /// (by then, all the systems should have been added)
+    ///
+    /// The result is logged as a warning (if anything is missing), and inserted
+    /// as a [`StateCoverageReport`] resource.
+    pub fn enable_coverage_check(&mut self) {
+        self.transitions().coverage_check = Some(T::variants);
+    }
+
+    /// Builder version of `enable_coverage_check`
+    pub fn with_coverage_check(mut self) -> Self {
+        self.enable_coverage_check();
+        self
+    }
+
+    /// Add a system to run when entering any of the states that match the filter
+    pub fn add_enter_system_for_all<Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) {
+        for state in T::variants().into_iter().filter(filter) {
+            self.add_enter_system(state, system.clone());
+        }
+    }
+
+    /// Add a system to run when exiting any of the states that match the filter
+    pub fn add_exit_system_for_all<Params>(&mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) {
+        for state in T::variants().into_iter().filter(filter) {
+            self.add_exit_system(state, system.clone());
+        }
+    }
+
+    /// Builder version of `add_enter_system_for_all`
+    pub fn with_enter_system_for_all<Params>(mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> Self {
+        self.add_enter_system_for_all(filter, system);
+        self
+    }
+
+    /// Builder version of `add_exit_system_for_all`
+    pub fn with_exit_system_for_all<Params>(mut self, filter: impl Fn(&T) -> bool, system: impl IntoSystemDescriptor<Params> + Clone) -> Self {
+        self.add_exit_system_for_all(filter, system);
+        self
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    fn coverage_report(&self, variants: Vec<T>) -> StateCoverageReport<T> {
+        let has_stage = |stages: &bevy_utils::HashMap<T, Vec<_>>, matching: &[(super::MatchFn<T>, _)], state: &T| {
+            stages.get(state).map(|stages| !stages.is_empty()).unwrap_or(false)
+                || matching.iter().any(|(predicate, _)| predicate(state))
+        };
+        let reachable = if self.has_allowed_transitions() {
+            self.reachable(&variants)
+        } else {
+            variants.clone()
+        };
+        StateCoverageReport {
+            missing_enter: variants.iter()
+                .filter(|state| !has_stage(&self.enter_stages, &self.enter_matching, state))
+                .cloned()
+                .collect(),
+            missing_exit: variants.iter()
+                .filter(|state| !has_stage(&self.exit_stages, &self.exit_matching, state))
+                .cloned()
+                .collect(),
+            unreachable: variants.iter()
+                .filter(|state| !reachable.contains(state))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Run the coverage check: log and store the report
+    pub(super) fn check_coverage(&self, world: &mut World, variants: Vec<T>) {
+        let report = self.coverage_report(variants);
+        if !report.is_complete() {
+            warn!(
+                "Incomplete state handling for state type {}: no enter stage for {:?}; no exit stage for {:?}; unreachable: {:?}",
+                std::any::type_name::<T>(),
+                report.missing_enter,
+                report.missing_exit,
+                report.unreachable,
+            );
+        }
+        world.insert_resource(report);
+    }
+}
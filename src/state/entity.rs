@@ -0,0 +1,521 @@
+//! Per-entity state machines
+//!
+//! This is the component-based counterpart of [`CurrentState`](super::CurrentState)
+//! and [`NextState`](super::NextState), for when you need a separate state machine
+//! for each entity (AI, animation, etc.).
+//!
+//! Every entity's current state is stored in an [`EntityState`] component. To
+//! change the state of an entity, insert a [`NextEntityState`] component on it.
+//! The [`EntityStateTransitionStage`] will perform the transitions, running the
+//! exit/enter stages once per state value, for all entities that are exiting/entering
+//! that state. The affected entities are available to the exit/enter systems
+//! via the [`TransitioningEntities`] resource.
+//!
+//! If you want to be able to filter queries by entity state, you can register
+//! marker components for state values (see [`EntityStateTransitionStage::set_state_marker`]).
+//! They will be inserted/removed automatically on transitions, so you can use
+//! them with `With<M>`/`Without<M>` query filters.
+
+use bevy_ecs::component::Component;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::schedule::{Stage, StateData, StageLabel, IntoSystemDescriptor, SystemSet, SystemStage};
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_utils::HashMap;
+
+use super::{report_transition_loop, DEFAULT_MAX_TRANSITIONS};
+
+type EntityFn = Box<dyn Fn(&mut World, Entity) + Send + Sync>;
+
+/// A marker component that exists on entities in a given state
+struct StateMarker {
+    insert: EntityFn,
+    remove: EntityFn,
+}
+
+/// The current state of an entity
+///
+/// Spawning an entity with this component does not run any enter systems.
+/// If you want the entity to enter its initial state "properly", spawn
+/// it with a [`NextEntityState`] instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Component)]
+pub struct EntityState<T: StateData>(pub T);
+
+/// When you want to change the state of an entity, insert this component
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Component)]
+pub struct NextEntityState<T: StateData>(pub T);
+
+/// Resource available to entity state enter/exit systems
+///
+/// Contains the state value being entered/exited and the list of entities
+/// that are entering/exiting it.
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct TransitioningEntities<T: StateData> {
+    /// The state being entered or exited
+    pub state: T,
+    /// The entities that are entering or exiting the state
+    pub entities: Vec<Entity>,
+}
+
+/// This stage serves as the "driver" for entity states of a given type
+///
+/// When this stage runs, it will look for entities with a [`NextEntityState`]
+/// component, and perform their state transitions:
+///  1. remove the `NextEntityState` components
+///  2. run the exit stage (if any) for every state being exited
+///  3. change the value of the `EntityState` components
+///  4. run the enter stage (if any) for every state being entered
+///
+/// Each exit/enter stage runs (at most) once per run of this stage, with all the
+/// affected entities listed in the [`TransitioningEntities`] resource.
+///
+/// Entities that do not have an `EntityState` yet will just enter their
+/// new state, with no exit stage.
+///
+/// The states are exited/entered in order of the first (lowest) entity
+/// transitioning out of/into each of them.
+///
+/// If more `NextEntityState` components are inserted from within the exit or
+/// enter stages, they are also processed, in the same run of this stage (up to
+/// a limit, see [`set_max_transitions`](Self::set_max_transitions)).
+pub struct EntityStateTransitionStage<T: StateData> {
+    /// The enter schedules of each state
+    enter_stages: HashMap<T, Box<dyn Stage>>,
+    /// The exit schedules of each state
+    exit_stages: HashMap<T, Box<dyn Stage>>,
+    /// Marker components for each state
+    markers: HashMap<T, Vec<StateMarker>>,
+    /// How many times the transitions can be repeated in a single run of the stage
+    max_transitions: usize,
+}
+
+impl<T: StateData> Default for EntityStateTransitionStage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: StateData> EntityStateTransitionStage<T> {
+    /// Create a new entity transitions stage for the given state type
+    pub fn new() -> Self {
+        Self {
+            enter_stages: Default::default(),
+            exit_stages: Default::default(),
+            markers: Default::default(),
+            max_transitions: DEFAULT_MAX_TRANSITIONS,
+        }
+    }
+
+    /// Set the maximum number of times the transitions can be repeated in a single run of the stage
+    ///
+    /// Exceeding it is treated as an error (an infinite transition loop): the
+    /// remaining `NextEntityState` components are discarded, and the stage panics,
+    /// or sends a [`StateTransitionLoop`](super::StateTransitionLoop) event (with the states of the entity that
+    /// transitioned the most), if you have added that event type to your app.
+    /// The default is 100.
+    pub fn set_max_transitions(&mut self, max_transitions: usize) {
+        self.max_transitions = max_transitions;
+    }
+
+    /// Builder version of `set_max_transitions`
+    pub fn with_max_transitions(mut self, max_transitions: usize) -> Self {
+        self.set_max_transitions(max_transitions);
+        self
+    }
+
+    /// Provide the stage to run when entities enter the given state
+    pub fn set_enter_stage<S: Stage>(&mut self, state: T, stage: S) {
+        self.enter_stages.insert(state, Box::new(stage));
+    }
+
+    /// Provide the stage to run when entities exit the given state
+    pub fn set_exit_stage<S: Stage>(&mut self, state: T, stage: S) {
+        self.exit_stages.insert(state, Box::new(stage));
+    }
+
+    /// Add a marker component to exist on entities while they are in the given state
+    ///
+    /// The component is inserted before the enter stage runs and removed after the
+    /// exit stage runs. You can use it in `With<M>` query filters, to select only
+    /// entities in that state.
+    pub fn set_state_marker<M: Component + Default>(&mut self, state: T) {
+        self.markers.entry(state).or_default().push(StateMarker {
+            insert: Box::new(|world: &mut World, entity: Entity| {
+                if let Some(mut entity) = world.get_entity_mut(entity) {
+                    entity.insert(M::default());
+                }
+            }),
+            remove: Box::new(|world: &mut World, entity: Entity| {
+                if let Some(mut entity) = world.get_entity_mut(entity) {
+                    entity.remove::<M>();
+                }
+            }),
+        });
+    }
+
+    /// Add a system to run when entities enter the given state
+    ///
+    /// Does not work if you have set a custom enter stage
+    /// of type other than `SystemStage`.
+    ///
+    /// Will create the enter `SystemStage` if it does not exist.
+    pub fn add_enter_system<Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) {
+        self.enter_stages.entry(state)
+            .or_insert_with(|| Box::new(SystemStage::parallel()))
+            .downcast_mut::<SystemStage>()
+            .expect("Entity state enter stage is not a SystemStage")
+            .add_system(system);
+    }
+
+    /// Add a system to run when entities exit the given state
+    ///
+    /// Does not work if you have set a custom exit stage
+    /// of type other than `SystemStage`.
+    ///
+    /// Will create the exit `SystemStage` if it does not exist.
+    pub fn add_exit_system<Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) {
+        self.exit_stages.entry(state)
+            .or_insert_with(|| Box::new(SystemStage::parallel()))
+            .downcast_mut::<SystemStage>()
+            .expect("Entity state exit stage is not a SystemStage")
+            .add_system(system);
+    }
+
+    /// Add a system set with multiple systems to run when entities enter the given state
+    ///
+    /// Does not work if you have set a custom enter stage
+    /// of type other than `SystemStage`.
+    ///
+    /// Will create the enter `SystemStage` if it does not exist.
+    pub fn add_enter_system_set(&mut self, state: T, system_set: SystemSet) {
+        self.enter_stages.entry(state)
+            .or_insert_with(|| Box::new(SystemStage::parallel()))
+            .downcast_mut::<SystemStage>()
+            .expect("Entity state enter stage is not a SystemStage")
+            .add_system_set(system_set);
+    }
+
+    /// Add a system set with multiple systems to run when entities exit the given state
+    ///
+    /// Does not work if you have set a custom exit stage
+    /// of type other than `SystemStage`.
+    ///
+    /// Will create the exit `SystemStage` if it does not exist.
+    pub fn add_exit_system_set(&mut self, state: T, system_set: SystemSet) {
+        self.exit_stages.entry(state)
+            .or_insert_with(|| Box::new(SystemStage::parallel()))
+            .downcast_mut::<SystemStage>()
+            .expect("Entity state exit stage is not a SystemStage")
+            .add_system_set(system_set);
+    }
+
+    /// Builder version of `set_enter_stage`
+    pub fn with_enter_stage<S: Stage>(mut self, state: T, stage: S) -> Self {
+        self.set_enter_stage(state, stage);
+        self
+    }
+
+    /// Builder version of `set_exit_stage`
+    pub fn with_exit_stage<S: Stage>(mut self, state: T, stage: S) -> Self {
+        self.set_exit_stage(state, stage);
+        self
+    }
+
+    /// Builder version of `set_state_marker`
+    pub fn with_state_marker<M: Component + Default>(mut self, state: T) -> Self {
+        self.set_state_marker::<M>(state);
+        self
+    }
+
+    /// Builder version of `add_enter_system`
+    pub fn with_enter_system<Params>(mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.add_enter_system(state, system);
+        self
+    }
+
+    /// Builder version of `add_exit_system`
+    pub fn with_exit_system<Params>(mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.add_exit_system(state, system);
+        self
+    }
+
+    /// Builder version of `add_enter_system_set`
+    pub fn with_enter_system_set(mut self, state: T, system_set: SystemSet) -> Self {
+        self.add_enter_system_set(state, system_set);
+        self
+    }
+
+    /// Builder version of `add_exit_system_set`
+    pub fn with_exit_system_set(mut self, state: T, system_set: SystemSet) -> Self {
+        self.add_exit_system_set(state, system_set);
+        self
+    }
+}
+
+impl<T: StateData> Stage for EntityStateTransitionStage<T> {
+    fn run(&mut self, world: &mut World) {
+        let mut n_passes = 0;
+        // the states each entity has transitioned through, for reporting loops
+        let mut paths: HashMap<Entity, Vec<T>> = Default::default();
+
+        loop {
+            let mut transitions: Vec<(Entity, Option<T>, T)> = world
+                .query::<(Entity, Option<&EntityState<T>>, &NextEntityState<T>)>()
+                .iter(world)
+                .map(|(entity, current, next)| (entity, current.map(|c| c.0.clone()), next.0.clone()))
+                .collect();
+
+            if transitions.is_empty() {
+                break;
+            }
+
+            // process everything in a deterministic order
+            transitions.sort_by_key(|(entity, _, _)| *entity);
+            for (entity, current, next) in transitions.iter() {
+                paths.entry(*entity)
+                    .or_insert_with(|| current.iter().cloned().collect())
+                    .push(next.clone());
+            }
+
+            n_passes += 1;
+            if n_passes > self.max_transitions {
+                for (entity, _, _) in transitions.iter() {
+                    world.entity_mut(*entity).remove::<NextEntityState<T>>();
+                }
+                let path = transitions.iter()
+                    .filter_map(|(entity, _, _)| paths.remove(entity))
+                    .max_by_key(|path| path.len())
+                    .expect("There should be transitioning entities");
+                report_transition_loop(world, path);
+                break;
+            }
+
+            // group the entities by the state they are exiting/entering,
+            // so that each exit/enter stage only runs once
+            let mut exiting: Vec<(T, Vec<Entity>)> = Vec::new();
+            let mut entering: Vec<(T, Vec<Entity>)> = Vec::new();
+            for (entity, current, next) in transitions {
+                world.entity_mut(entity).remove::<NextEntityState<T>>();
+                if let Some(current) = current {
+                    add_to_group(&mut exiting, current, entity);
+                }
+                add_to_group(&mut entering, next, entity);
+            }
+
+            for (state, entities) in exiting {
+                if let Some(stage) = self.exit_stages.get_mut(&state) {
+                    world.insert_resource(TransitioningEntities {
+                        state: state.clone(),
+                        entities: entities.clone(),
+                    });
+                    stage.run(world);
+                }
+                if let Some(markers) = self.markers.get(&state) {
+                    for &entity in entities.iter() {
+                        for marker in markers.iter() {
+                            (marker.remove)(world, entity);
+                        }
+                    }
+                }
+            }
+
+            for (state, entities) in entering {
+                // the entities could have been despawned by exit systems
+                let entities: Vec<Entity> = entities.into_iter()
+                    .filter(|entity| world.get_entity(*entity).is_some())
+                    .collect();
+                for &entity in entities.iter() {
+                    world.entity_mut(entity).insert(EntityState(state.clone()));
+                }
+                if let Some(markers) = self.markers.get(&state) {
+                    for &entity in entities.iter() {
+                        for marker in markers.iter() {
+                            (marker.insert)(world, entity);
+                        }
+                    }
+                }
+                if let Some(stage) = self.enter_stages.get_mut(&state) {
+                    world.insert_resource(TransitioningEntities {
+                        state,
+                        entities,
+                    });
+                    stage.run(world);
+                }
+            }
+
+            world.remove_resource::<TransitioningEntities<T>>();
+        }
+    }
+}
+
+/// Add an entity to the group of a state, creating the group if needed
+fn add_to_group<T: StateData>(groups: &mut Vec<(T, Vec<Entity>)>, state: T, entity: Entity) {
+    match groups.iter_mut().find(|(group_state, _)| *group_state == state) {
+        Some((_, entities)) => entities.push(entity),
+        None => groups.push((state, vec![entity])),
+    }
+}
+
+/// Type used as a Bevy Stage Label for entity state transition stages
+#[derive(Debug, Clone)]
+pub struct EntityStateTransitionStageLabel(&'static str);
+
+impl StageLabel for EntityStateTransitionStageLabel {
+    fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl EntityStateTransitionStageLabel {
+    /// Construct the label for a stage to drive the entity state type T
+    pub fn from_type<T: StateData>() -> Self {
+        EntityStateTransitionStageLabel(super::type_label::<EntityStateTransitionStage<T>>())
+    }
+}
+
+/// Extensions to `bevy_app`
+#[cfg(feature = "app")]
+pub mod app {
+    use bevy_ecs::component::Component;
+    use bevy_ecs::schedule::{StageLabel, StateData, IntoSystemDescriptor};
+    use bevy_app::{App, CoreStage};
+
+    use super::{EntityStateTransitionStage, EntityStateTransitionStageLabel};
+
+    /// Extension trait with the methods to add to Bevy's `App`
+    ///
+    /// Unlike the methods of `AppLooplessStateExt`, these do not buffer registrations:
+    /// add the entity state (`add_entity_state*`) before configuring it.
+    pub trait AppLooplessEntityStateExt {
+        /// Add an `EntityStateTransitionStage` in the default position
+        ///
+        /// (before `CoreStage::Update`)
+        fn add_entity_state<T: StateData>(&mut self) -> &mut App;
+        /// Add an `EntityStateTransitionStage` after the specified stage
+        fn add_entity_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App;
+        /// Add an `EntityStateTransitionStage` before the specified stage
+        fn add_entity_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App;
+        /// Add an enter system for the given entity state
+        ///
+        /// Requires the stage to be labeled with a `EntityStateTransitionStageLabel`
+        /// (as done by the `add_entity_state*` methods).
+        fn add_entity_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add an exit system for the given entity state
+        ///
+        /// Requires the stage to be labeled with a `EntityStateTransitionStageLabel`
+        /// (as done by the `add_entity_state*` methods).
+        fn add_entity_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+        /// Add a marker component to exist on entities while they are in the given state
+        ///
+        /// Requires the stage to be labeled with a `EntityStateTransitionStageLabel`
+        /// (as done by the `add_entity_state*` methods).
+        fn add_entity_state_marker<T: StateData, M: Component + Default>(&mut self, state: T) -> &mut App;
+    }
+
+    impl AppLooplessEntityStateExt for App {
+        fn add_entity_state<T: StateData>(&mut self) -> &mut App {
+            self.add_entity_state_before_stage::<T>(CoreStage::Update)
+        }
+        fn add_entity_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App {
+            self.add_stage_after(
+                stage,
+                EntityStateTransitionStageLabel::from_type::<T>(),
+                EntityStateTransitionStage::<T>::new()
+            )
+        }
+        fn add_entity_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut App {
+            self.add_stage_before(
+                stage,
+                EntityStateTransitionStageLabel::from_type::<T>(),
+                EntityStateTransitionStage::<T>::new()
+            )
+        }
+        fn add_entity_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let stage = self.schedule.get_stage_mut::<EntityStateTransitionStage<T>>(EntityStateTransitionStageLabel::from_type::<T>())
+                .expect("Entity State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_system(state, system);
+            self
+        }
+        fn add_entity_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+            let stage = self.schedule.get_stage_mut::<EntityStateTransitionStage<T>>(EntityStateTransitionStageLabel::from_type::<T>())
+                .expect("Entity State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_system(state, system);
+            self
+        }
+        fn add_entity_state_marker<T: StateData, M: Component + Default>(&mut self, state: T) -> &mut App {
+            let stage = self.schedule.get_stage_mut::<EntityStateTransitionStage<T>>(EntityStateTransitionStageLabel::from_type::<T>())
+                .expect("Entity State Transition Stage not found (assuming auto-added label)");
+            stage.set_state_marker::<M>(state);
+            self
+        }
+    }
+}
+
+/// Extensions to Bevy Schedule
+pub mod schedule {
+    use bevy_ecs::component::Component;
+    use bevy_ecs::schedule::{StageLabel, StateData, IntoSystemDescriptor, Schedule};
+
+    use super::{EntityStateTransitionStage, EntityStateTransitionStageLabel};
+
+    /// Extension trait with the methods to add to Bevy's `Schedule`
+    pub trait ScheduleLooplessEntityStateExt {
+        /// Add an `EntityStateTransitionStage` after the specified stage
+        fn add_entity_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule;
+        /// Add an `EntityStateTransitionStage` before the specified stage
+        fn add_entity_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule;
+        /// Add an enter system for the given entity state
+        ///
+        /// Requires the stage to be labeled with a `EntityStateTransitionStageLabel`
+        /// (as done by the `add_entity_state*` methods).
+        fn add_entity_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add an exit system for the given entity state
+        ///
+        /// Requires the stage to be labeled with a `EntityStateTransitionStageLabel`
+        /// (as done by the `add_entity_state*` methods).
+        fn add_entity_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule;
+        /// Add a marker component to exist on entities while they are in the given state
+        ///
+        /// Requires the stage to be labeled with a `EntityStateTransitionStageLabel`
+        /// (as done by the `add_entity_state*` methods).
+        fn add_entity_state_marker<T: StateData, M: Component + Default>(&mut self, state: T) -> &mut Schedule;
+    }
+
+    impl ScheduleLooplessEntityStateExt for Schedule {
+        fn add_entity_state_after_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule {
+            self.add_stage_after(
+                stage,
+                EntityStateTransitionStageLabel::from_type::<T>(),
+                EntityStateTransitionStage::<T>::new()
+            )
+        }
+        fn add_entity_state_before_stage<T: StateData>(&mut self, stage: impl StageLabel) -> &mut Schedule {
+            self.add_stage_before(
+                stage,
+                EntityStateTransitionStageLabel::from_type::<T>(),
+                EntityStateTransitionStage::<T>::new()
+            )
+        }
+        fn add_entity_enter_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<EntityStateTransitionStage<T>>(EntityStateTransitionStageLabel::from_type::<T>())
+                .expect("Entity State Transition Stage not found (assuming auto-added label)");
+            stage.add_enter_system(state, system);
+            self
+        }
+        fn add_entity_exit_system<T: StateData, Params>(&mut self, state: T, system: impl IntoSystemDescriptor<Params>) -> &mut Schedule {
+            let stage = self.get_stage_mut::<EntityStateTransitionStage<T>>(EntityStateTransitionStageLabel::from_type::<T>())
+                .expect("Entity State Transition Stage not found (assuming auto-added label)");
+            stage.add_exit_system(state, system);
+            self
+        }
+        fn add_entity_state_marker<T: StateData, M: Component + Default>(&mut self, state: T) -> &mut Schedule {
+            let stage = self.get_stage_mut::<EntityStateTransitionStage<T>>(EntityStateTransitionStageLabel::from_type::<T>())
+                .expect("Entity State Transition Stage not found (assuming auto-added label)");
+            stage.set_state_marker::<M>(state);
+            self
+        }
+    }
+}
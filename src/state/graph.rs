@@ -0,0 +1,254 @@
+//! Visualizing state machines, as Graphviz DOT or Mermaid diagrams
+//!
+//! [`StateTransitionStage::state_graph`] describes the states that the stage
+//! knows about (those that have enter/exit/re-enter stages, or any other
+//! per-state configuration), and the transitions between them. Render it
+//! using [`StateGraph::to_dot`] or [`StateGraph::to_mermaid`].
+//!
+//! The transitions shown are the declared allowed transitions (see the
+//! [`allowed`](super::allowed) module), and those that have been observed at
+//! runtime, if tracking is enabled with [`StateTransitionStage::enable_transition_tracking`].
+//! With tracking, the edges are annotated with how many times each transition happened.
+
+use bevy_ecs::schedule::StateData;
+use bevy_utils::HashMap;
+
+use std::fmt::Write;
+
+use super::coverage::LooplessStates;
+use super::{StateTransitionStage, StateTransitions};
+
+/// A description of a state machine, for visualization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateGraph<T> {
+    /// The name of the state type
+    pub name: &'static str,
+    /// The states
+    pub states: Vec<StateGraphNode<T>>,
+    /// The transitions between them
+    pub transitions: Vec<StateGraphEdge<T>>,
+}
+
+/// A state in a [`StateGraph`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateGraphNode<T> {
+    /// The state value
+    pub state: T,
+    /// Is this the starting state?
+    pub initial: bool,
+    /// Does it have enter stages?
+    pub enter: bool,
+    /// Does it have exit stages?
+    pub exit: bool,
+    /// Does it have a re-enter stage?
+    pub reenter: bool,
+}
+
+/// A transition in a [`StateGraph`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateGraphEdge<T> {
+    /// The state we are transitioning from
+    pub from: T,
+    /// The state we are transitioning to
+    pub to: T,
+    /// How many times the transition has been observed (if tracking is enabled)
+    pub count: Option<u64>,
+    /// Is the transition allowed? (always `true` if no allowed transitions were declared)
+    pub allowed: bool,
+}
+
+impl<T: StateData> StateTransitionStage<T> {
+    /// Describe the states and transitions that this stage knows about
+    pub fn state_graph(&self) -> StateGraph<T> {
+        self.transitions().state_graph(Vec::new())
+    }
+
+    /// Count the transitions performed at runtime, to show them in the [`StateGraph`]
+    pub fn enable_transition_tracking(&mut self) {
+        let mut transitions = self.transitions();
+        if transitions.observed.is_none() {
+            transitions.observed = Some(HashMap::default());
+        }
+    }
+
+    /// Builder version of `enable_transition_tracking`
+    pub fn with_transition_tracking(mut self) -> Self {
+        self.enable_transition_tracking();
+        self
+    }
+
+    /// How many times each transition has happened (if tracking is enabled)
+    pub fn observed_transitions(&self) -> Vec<(T, T, u64)> {
+        self.transitions().observed.iter()
+            .flatten()
+            .map(|((from, to), count)| (from.clone(), to.clone(), *count))
+            .collect()
+    }
+}
+
+impl<T: LooplessStates> StateTransitionStage<T> {
+    /// Like [`state_graph`](Self::state_graph), but including all the values of the state type
+    pub fn full_state_graph(&self) -> StateGraph<T> {
+        self.transitions().state_graph(T::variants())
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    /// Count a transition (if tracking is enabled)
+    pub(super) fn observe_transition(&mut self, from: &T, to: &T) {
+        if let Some(observed) = &mut self.observed {
+            *observed.entry((from.clone(), to.clone())).or_default() += 1;
+        }
+    }
+
+    fn state_graph(&self, variants: Vec<T>) -> StateGraph<T> {
+        // the given variants come first, in order; then everything else we know about
+        let mut extra: Vec<T> = self.enter_stages.keys()
+            .chain(self.exit_stages.keys())
+            .chain(self.reenter_stages.keys())
+            .chain(self.scoped_resources.keys())
+            .chain(self.readiness.keys())
+            .chain(self.default.iter())
+            .chain(self.allowed.iter().flat_map(|(from, to)| [from, to]))
+            .chain(self.allowed_any_from.iter())
+            .chain(self.observed.iter().flatten().flat_map(|((from, to), _)| [from, to]))
+            .filter(|state| !variants.contains(state))
+            .cloned()
+            .collect();
+        extra.sort_by_key(|state| format!("{:?}", state));
+        extra.dedup();
+
+        let has_stage = |stages: &HashMap<T, Vec<_>>, matching: &[(super::MatchFn<T>, _)], state: &T| {
+            stages.get(state).map(|stages| !stages.is_empty()).unwrap_or(false)
+                || matching.iter().any(|(predicate, _)| predicate(state))
+        };
+        let states = variants.into_iter()
+            .chain(extra)
+            .map(|state| StateGraphNode {
+                initial: self.default.as_ref() == Some(&state),
+                enter: has_stage(&self.enter_stages, &self.enter_matching, &state),
+                exit: has_stage(&self.exit_stages, &self.exit_matching, &state),
+                reenter: self.reenter_stages.contains_key(&state),
+                state,
+            })
+            .collect::<Vec<_>>();
+
+        let index = |state: &T| states.iter().position(|node| node.state == *state);
+        // declared transitions; "any from" ones are shown from every other known state
+        let declared = self.allowed.iter()
+            .cloned()
+            .chain(self.allowed_any_from.iter().flat_map(|to| {
+                states.iter()
+                    .filter(move |node| node.state != *to)
+                    .map(move |node| (node.state.clone(), to.clone()))
+            }));
+        let mut edges: HashMap<(T, T), StateGraphEdge<T>> = HashMap::default();
+        for (from, to) in declared {
+            let count = self.observed.as_ref()
+                .map(|observed| observed.get(&(from.clone(), to.clone())).copied().unwrap_or(0));
+            edges.insert((from.clone(), to.clone()), StateGraphEdge { from, to, count, allowed: true });
+        }
+        for ((from, to), count) in self.observed.iter().flatten() {
+            edges.entry((from.clone(), to.clone())).or_insert_with(|| StateGraphEdge {
+                from: from.clone(),
+                to: to.clone(),
+                count: Some(*count),
+                allowed: self.is_transition_allowed(from, to),
+            });
+        }
+        let mut transitions: Vec<_> = edges.into_values().collect();
+        transitions.sort_by_key(|edge| (index(&edge.from), index(&edge.to)));
+
+        StateGraph {
+            name: std::any::type_name::<T>(),
+            states,
+            transitions,
+        }
+    }
+}
+
+impl<T: StateData> StateGraph<T> {
+    /// Render as a Graphviz DOT digraph
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph {} {{", dot_quote(self.name)).unwrap();
+        for node in self.states.iter() {
+            let name = format!("{:?}", node.state);
+            let mut label = name.clone();
+            let stages = node.stage_list();
+            if !stages.is_empty() {
+                label.push('\n');
+                label.push_str(&stages);
+            }
+            write!(out, "    {} [label={}", dot_quote(&name), dot_quote(&label)).unwrap();
+            if node.initial {
+                out.push_str(", peripheries=2");
+            }
+            out.push_str("];\n");
+        }
+        for edge in self.transitions.iter() {
+            write!(
+                out, "    {} -> {}",
+                dot_quote(&format!("{:?}", edge.from)),
+                dot_quote(&format!("{:?}", edge.to)),
+            ).unwrap();
+            if let Some(count) = edge.count {
+                write!(out, " [label=\"{}\"]", count).unwrap();
+            }
+            out.push_str(";\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render as a Mermaid state diagram
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("stateDiagram-v2\n");
+        let id = |state: &T| {
+            let i = self.states.iter()
+                .position(|node| node.state == *state)
+                .unwrap_or(self.states.len());
+            format!("s{}", i)
+        };
+        for node in self.states.iter() {
+            let mut label = format!("{:?}", node.state);
+            let stages = node.stage_list();
+            if !stages.is_empty() {
+                write!(label, " ({})", stages).unwrap();
+            }
+            writeln!(out, "    state \"{}\" as {}", mermaid_escape(&label), id(&node.state)).unwrap();
+        }
+        for node in self.states.iter().filter(|node| node.initial) {
+            writeln!(out, "    [*] --> {}", id(&node.state)).unwrap();
+        }
+        for edge in self.transitions.iter() {
+            write!(out, "    {} --> {}", id(&edge.from), id(&edge.to)).unwrap();
+            if let Some(count) = edge.count {
+                write!(out, ": {}", count).unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl<T> StateGraphNode<T> {
+    /// Which kinds of stages the state has, like `"enter, exit"`
+    fn stage_list(&self) -> String {
+        [(self.enter, "enter"), (self.exit, "exit"), (self.reenter, "reenter")].iter()
+            .filter(|(has, _)| *has)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Quote a string for use as a DOT identifier
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Escape a string for use in a Mermaid label
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
@@ -0,0 +1,75 @@
+//! Readiness gates: waiting for something before entering a state
+//!
+//! Add "readiness systems" for a state, which return `bool`. When a transition
+//! to that state is requested, all of them must return `true` before it is
+//! performed. Until then, the request stays pending, and is available as a
+//! [`PendingTransition`] resource (for example, to show a loading screen).
+//!
+//! The readiness systems run every time the `StateTransitionStage` runs, while
+//! the transition is pending. A new request (like a different [`NextState`](super::NextState))
+//! replaces the pending one. The `PendingTransition` resource is left untouched
+//! while the same request is pending, so change detection only triggers for new requests.
+
+use bevy_ecs::schedule::StateData;
+use bevy_ecs::system::{IntoSystem, Resource, System};
+use bevy_ecs::world::World;
+
+use super::{StateTransitionStage, StateTransitions};
+
+type BoxedCondition = Box<dyn System<In = (), Out = bool>>;
+
+/// Resource that exists while a requested transition is waiting for its readiness systems
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct PendingTransition<T> {
+    /// The current state
+    pub from: T,
+    /// The requested state
+    pub to: T,
+    pub(super) going_back: bool,
+}
+
+/// A system that must return `true` before entering a state
+pub(super) struct ReadinessSystem {
+    system: BoxedCondition,
+    initialized: bool,
+}
+
+impl<T: StateData> StateTransitionStage<T> {
+    /// Add a system that must return `true` before entering the given state
+    ///
+    /// See the [`readiness`](super::readiness) module.
+    pub fn add_readiness_system<Params>(&mut self, state: T, system: impl IntoSystem<(), bool, Params>) {
+        self.transitions().readiness.entry(state).or_default().push(ReadinessSystem {
+            system: Box::new(IntoSystem::into_system(system)),
+            initialized: false,
+        });
+    }
+
+    /// Builder version of `add_readiness_system`
+    pub fn with_readiness_system<Params>(mut self, state: T, system: impl IntoSystem<(), bool, Params>) -> Self {
+        self.add_readiness_system(state, system);
+        self
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    /// Run all the readiness systems of the given state
+    ///
+    /// Returns `true` if all of them are ready (or there are none).
+    pub(super) fn check_readiness(&mut self, world: &mut World, state: &T) -> bool {
+        let mut ready = true;
+        if let Some(systems) = self.readiness.get_mut(state) {
+            // run all of them, even if one is not ready, as they might be polling something
+            for readiness in systems.iter_mut() {
+                if !readiness.initialized {
+                    readiness.system.initialize(world);
+                    readiness.initialized = true;
+                }
+                ready &= readiness.system.run((), world);
+                readiness.system.apply_buffers(world);
+            }
+        }
+        ready
+    }
+}
@@ -0,0 +1,245 @@
+//! `Reflect` support for [`CurrentState`] and [`NextState`]
+//!
+//! Implemented for any state type that implements `Reflect` (and `FromReflect`),
+//! so that states can be seen and edited by reflection-based tools, like
+//! `bevy-inspector-egui`'s world inspector.
+//!
+//! The types need to be registered in the `AppTypeRegistry`, like any other
+//! reflected type. `add_loopless_state` does not require the state type to be
+//! `Reflect`, so it cannot do this for you. Use `register_loopless_state_type`.
+
+use bevy_ecs::reflect::{ReflectResource, ReflectResourceFns};
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_reflect::utility::GenericTypeInfoCell;
+use bevy_reflect::{
+    DynamicTupleStruct, FromReflect, FromType, GetTypeRegistration, Reflect, ReflectFromPtr,
+    ReflectMut, ReflectOwned, ReflectRef, TupleStruct, TupleStructFieldIter, TupleStructInfo,
+    TypeInfo, TypeRegistration, Typed, UnnamedField,
+};
+
+use super::{CurrentState, NextState};
+
+/// `Reflect` for a newtype wrapper around the state value
+///
+/// (`#[derive(Reflect)]` cannot be used, as it requires the bounds on the struct)
+macro_rules! impl_reflect_state_wrapper {
+    ($wrapper:ident) => {
+        impl<T: FromReflect> TupleStruct for $wrapper<T> {
+            fn field(&self, index: usize) -> Option<&dyn Reflect> {
+                match index {
+                    0 => Some(&self.0),
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, index: usize) -> Option<&mut dyn Reflect> {
+                match index {
+                    0 => Some(&mut self.0),
+                    _ => None,
+                }
+            }
+
+            fn field_len(&self) -> usize {
+                1
+            }
+
+            fn iter_fields(&self) -> TupleStructFieldIter<'_> {
+                TupleStructFieldIter::new(self)
+            }
+
+            fn clone_dynamic(&self) -> DynamicTupleStruct {
+                let mut dynamic = DynamicTupleStruct::default();
+                dynamic.set_name(self.type_name().to_string());
+                dynamic.insert_boxed(self.0.clone_value());
+                dynamic
+            }
+        }
+
+        impl<T: FromReflect> Reflect for $wrapper<T> {
+            fn type_name(&self) -> &str {
+                std::any::type_name::<Self>()
+            }
+
+            fn get_type_info(&self) -> &'static TypeInfo {
+                <Self as Typed>::type_info()
+            }
+
+            fn into_any(self: Box<Self>) -> Box<dyn std::any::Any> {
+                self
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                self
+            }
+
+            fn into_reflect(self: Box<Self>) -> Box<dyn Reflect> {
+                self
+            }
+
+            fn as_reflect(&self) -> &dyn Reflect {
+                self
+            }
+
+            fn as_reflect_mut(&mut self) -> &mut dyn Reflect {
+                self
+            }
+
+            fn clone_value(&self) -> Box<dyn Reflect> {
+                Box::new(self.clone_dynamic())
+            }
+
+            fn set(&mut self, value: Box<dyn Reflect>) -> Result<(), Box<dyn Reflect>> {
+                *self = value.take()?;
+                Ok(())
+            }
+
+            fn apply(&mut self, value: &dyn Reflect) {
+                if let ReflectRef::TupleStruct(value) = value.reflect_ref() {
+                    if let Some(field) = value.field(0) {
+                        self.0.apply(field);
+                    }
+                } else {
+                    panic!("Attempted to apply non-TupleStruct type to TupleStruct type.");
+                }
+            }
+
+            fn reflect_ref(&self) -> ReflectRef<'_> {
+                ReflectRef::TupleStruct(self)
+            }
+
+            fn reflect_mut(&mut self) -> ReflectMut<'_> {
+                ReflectMut::TupleStruct(self)
+            }
+
+            fn reflect_owned(self: Box<Self>) -> ReflectOwned {
+                ReflectOwned::TupleStruct(self)
+            }
+
+            fn reflect_partial_eq(&self, value: &dyn Reflect) -> Option<bool> {
+                bevy_reflect::tuple_struct_partial_eq(self, value)
+            }
+        }
+
+        impl<T: FromReflect> Typed for $wrapper<T> {
+            fn type_info() -> &'static TypeInfo {
+                static CELL: GenericTypeInfoCell = GenericTypeInfoCell::new();
+                CELL.get_or_insert::<Self, _>(|| {
+                    let fields = [UnnamedField::new::<T>(0)];
+                    TypeInfo::TupleStruct(TupleStructInfo::new::<Self>(stringify!($wrapper), &fields))
+                })
+            }
+        }
+
+        impl<T: FromReflect> FromReflect for $wrapper<T> {
+            fn from_reflect(reflect: &dyn Reflect) -> Option<Self> {
+                if let ReflectRef::TupleStruct(value) = reflect.reflect_ref() {
+                    Some($wrapper(T::from_reflect(value.field(0)?)?))
+                } else {
+                    None
+                }
+            }
+        }
+
+        impl<T: FromReflect> GetTypeRegistration for $wrapper<T> {
+            fn get_type_registration() -> TypeRegistration {
+                let mut registration = TypeRegistration::of::<Self>();
+                registration.insert::<ReflectFromPtr>(FromType::<Self>::from_type());
+                registration.insert::<ReflectResource>(reflect_resource::<Self>());
+                registration
+            }
+        }
+    };
+}
+
+impl_reflect_state_wrapper!(CurrentState);
+impl_reflect_state_wrapper!(NextState);
+
+/// Placeholder, needed to construct a custom `ReflectResource`
+#[derive(Default)]
+#[derive(Resource, Reflect)]
+struct Placeholder;
+
+/// `ReflectResource` for a resource that does not impl `FromWorld`
+///
+/// New values are created using `FromReflect` instead.
+fn reflect_resource<R: Resource + FromReflect>() -> ReflectResource {
+    fn from_reflect<R: FromReflect>(reflected: &dyn Reflect) -> R {
+        R::from_reflect(reflected)
+            .unwrap_or_else(|| panic!("Cannot create a {} from the reflected value", std::any::type_name::<R>()))
+    }
+
+    let fns = ReflectResourceFns {
+        insert: |world, reflected| {
+            world.insert_resource(from_reflect::<R>(reflected));
+        },
+        apply: |world, reflected| {
+            world.resource_mut::<R>().apply(reflected);
+        },
+        apply_or_insert: |world, reflected| {
+            if let Some(mut resource) = world.get_resource_mut::<R>() {
+                resource.apply(reflected);
+            } else {
+                world.insert_resource(from_reflect::<R>(reflected));
+            }
+        },
+        remove: |world| {
+            world.remove_resource::<R>();
+        },
+        reflect: |world| world.get_resource::<R>().map(|res| res as &dyn Reflect),
+        reflect_unchecked_mut: |world| {
+            // SAFETY: same as the implementation in `bevy_ecs`; the caller
+            // guarantees that there are no other references to the resource
+            unsafe {
+                world.get_resource_unchecked_mut::<R>()
+                    .map(|res| res.map_unchanged(|res| res as &mut dyn Reflect))
+            }
+        },
+        copy: |source, destination: &mut World| {
+            let resource = from_reflect::<R>(source.resource::<R>());
+            destination.insert_resource(resource);
+        },
+    };
+    // (`ReflectResource::new` takes `&self` in this version of Bevy)
+    <ReflectResource as FromType<Placeholder>>::from_type().new(fns)
+}
+
+/// Extensions to Bevy App
+#[cfg(feature = "app")]
+pub mod app {
+    use bevy_ecs::schedule::StateData;
+    use bevy_app::App;
+    use bevy_reflect::{FromReflect, GetTypeRegistration};
+
+    use crate::state::app::AppLooplessStateExt;
+    use crate::state::{CurrentState, NextState};
+
+    /// Extension trait with the methods to add to Bevy's `App`
+    pub trait AppLooplessStateReflectExt {
+        /// Add a `StateTransitionStage` in the default position, and register
+        /// the state type in the `AppTypeRegistry`
+        ///
+        /// `add_loopless_state` does not require the state type to be `Reflect`,
+        /// so it cannot register it for you.
+        fn add_reflected_loopless_state<T: StateData + FromReflect + GetTypeRegistration>(&mut self, init: T) -> &mut App;
+        /// Register the state type, and its `CurrentState`/`NextState` resources,
+        /// in the `AppTypeRegistry`
+        fn register_loopless_state_type<T: StateData + FromReflect + GetTypeRegistration>(&mut self) -> &mut App;
+    }
+
+    impl AppLooplessStateReflectExt for App {
+        fn add_reflected_loopless_state<T: StateData + FromReflect + GetTypeRegistration>(&mut self, init: T) -> &mut App {
+            self.add_loopless_state(init);
+            self.register_loopless_state_type::<T>()
+        }
+        fn register_loopless_state_type<T: StateData + FromReflect + GetTypeRegistration>(&mut self) -> &mut App {
+            self.register_type::<T>()
+                .register_type::<CurrentState<T>>()
+                .register_type::<NextState<T>>()
+        }
+    }
+}
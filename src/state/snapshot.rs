@@ -0,0 +1,197 @@
+//! Saving and restoring the values of states (for save games)
+//!
+//! State types need to be registered (see [`register_serializable_state`]),
+//! and must implement `Serialize` and `Deserialize`. Then, you can take a
+//! [`StateSnapshot`] of all of them, using [`snapshot_states`]. It can be
+//! serialized with any `serde` format.
+//!
+//! Every state type is registered with a key, that identifies it in the
+//! snapshot. Keep the keys the same across versions of your game, so that
+//! old snapshots can still be restored.
+//!
+//! Restoring a snapshot using [`restore_states`] does not change the states
+//! immediately. It requests a transition to each of the saved values, using
+//! [`NextState`], so that the appropriate exit/enter stages are run, the next
+//! time the `StateTransitionStage` of each state type runs. These transitions
+//! are performed even if they are not [allowed](super::allowed) from the
+//! current state (readiness systems still apply).
+
+use bevy_ecs::schedule::StateData;
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_utils::HashMap;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use std::any::TypeId;
+
+use super::allowed::UncheckedRequest;
+use super::{CurrentState, NextState};
+
+/// The saved values of all registered state types
+///
+/// The values are stored by the key the state type was registered with.
+/// Each value is encoded as a JSON string, so that the snapshot itself does
+/// not depend on the format it is serialized with (including formats that are
+/// not self-describing).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+pub struct StateSnapshot {
+    states: HashMap<String, String>,
+}
+
+impl StateSnapshot {
+    /// Get the saved value of the state type registered with the given key, if any
+    pub fn get<T: StateData + DeserializeOwned>(&self, key: &str) -> Option<Result<T, serde_json::Error>> {
+        self.states.get(key)
+            .map(|value| serde_json::from_str(value))
+    }
+
+    /// Is the snapshot empty?
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+/// A state type that can be saved and restored
+struct SerializableState {
+    key: &'static str,
+    type_id: TypeId,
+    type_name: &'static str,
+    save: fn(&World) -> Option<Result<String, serde_json::Error>>,
+    load: fn(&mut World, &str) -> Result<(), serde_json::Error>,
+}
+
+/// Resource with all the state types that can be saved and restored
+#[derive(Default)]
+#[derive(Resource)]
+pub struct StateRegistry {
+    states: Vec<SerializableState>,
+}
+
+impl StateRegistry {
+    /// Iterate over the keys of the registered state types
+    pub fn keys(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.states.iter().map(|state| state.key)
+    }
+}
+
+/// Register a state type, to be included in state snapshots
+///
+/// The `key` identifies the state type in snapshots. It should be unique and
+/// stable (unlike type names, which can change between compiler versions).
+///
+/// Registering the same type with the same key multiple times has no effect.
+/// Panics if the key is already used by a different state type, or if the
+/// state type is already registered with a different key.
+pub fn register_serializable_state<T: StateData + Serialize + DeserializeOwned>(world: &mut World, key: &'static str) {
+    let type_id = TypeId::of::<T>();
+    let type_name = std::any::type_name::<T>();
+    let mut registry = world.get_resource_or_insert_with(StateRegistry::default);
+    if let Some(state) = registry.states.iter().find(|state| state.key == key || state.type_id == type_id) {
+        if state.key == key && state.type_id == type_id {
+            return;
+        }
+        panic!(
+            "Cannot register state type {} with snapshot key {:?}: conflicts with state type {} (key {:?})",
+            type_name, key, state.type_name, state.key,
+        );
+    }
+    registry.states.push(SerializableState {
+        key,
+        type_id,
+        type_name,
+        save: |world| {
+            world.get_resource::<CurrentState<T>>()
+                .map(|current| serde_json::to_string(&current.0))
+        },
+        load: |world, value| {
+            let state: T = serde_json::from_str(value)?;
+            world.insert_resource(UncheckedRequest(state.clone()));
+            world.insert_resource(NextState(state));
+            Ok(())
+        },
+    });
+}
+
+/// Save the current values of all registered state types
+///
+/// State types that do not currently exist (there is no `CurrentState` resource)
+/// are not included.
+pub fn snapshot_states(world: &World) -> Result<StateSnapshot, serde_json::Error> {
+    let mut snapshot = StateSnapshot::default();
+    if let Some(registry) = world.get_resource::<StateRegistry>() {
+        for state in registry.states.iter() {
+            if let Some(value) = (state.save)(world) {
+                snapshot.states.insert(state.key.to_owned(), value?);
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Restore the values of all registered state types from a snapshot
+///
+/// Inserts a [`NextState`] for every state type that is in the snapshot,
+/// so that its exit/enter stages will run. State types that are not
+/// registered are ignored.
+///
+/// If any of the values cannot be deserialized, returns an error, and
+/// does not restore any states.
+pub fn restore_states(world: &mut World, snapshot: &StateSnapshot) -> Result<(), serde_json::Error> {
+    let loaders: Vec<_> = match world.get_resource::<StateRegistry>() {
+        Some(registry) => registry.states.iter()
+            .filter_map(|state| {
+                snapshot.states.get(state.key).map(|value| (state.load, value.clone()))
+            })
+            .collect(),
+        None => return Ok(()),
+    };
+
+    // apply the values to a scratch world first, to validate them all
+    let mut scratch = World::new();
+    for (load, value) in loaders.iter() {
+        load(&mut scratch, value)?;
+    }
+
+    for (load, value) in loaders {
+        load(world, &value)?;
+    }
+    Ok(())
+}
+
+/// Extensions to Bevy App
+#[cfg(feature = "app")]
+pub mod app {
+    use bevy_ecs::schedule::StateData;
+    use bevy_app::App;
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+
+    use crate::state::app::AppLooplessStateExt;
+
+    /// Extension trait with the methods to add to Bevy's `App`
+    pub trait AppLooplessStateSnapshotExt {
+        /// Add a `StateTransitionStage` in the default position, and register
+        /// the state type to be included in state snapshots, with the given key
+        ///
+        /// `add_loopless_state` does not require the state type to be serializable,
+        /// so it cannot register it for you.
+        fn add_serializable_loopless_state<T: StateData + Serialize + DeserializeOwned>(&mut self, key: &'static str, init: T) -> &mut App;
+        /// Register a state type to be included in state snapshots, with the given key
+        ///
+        /// See [`register_serializable_state`](super::register_serializable_state).
+        fn register_serializable_state<T: StateData + Serialize + DeserializeOwned>(&mut self, key: &'static str) -> &mut App;
+    }
+
+    impl AppLooplessStateSnapshotExt for App {
+        fn add_serializable_loopless_state<T: StateData + Serialize + DeserializeOwned>(&mut self, key: &'static str, init: T) -> &mut App {
+            self.add_loopless_state(init);
+            self.register_serializable_state::<T>(key)
+        }
+        fn register_serializable_state<T: StateData + Serialize + DeserializeOwned>(&mut self, key: &'static str) -> &mut App {
+            super::register_serializable_state::<T>(&mut self.world, key);
+            self
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Timed transitions, for things like fading between states
+//!
+//! If a transition duration is set on the [`StateTransitionStage`], state
+//! transitions are not performed immediately. Instead, a [`Transitioning`]
+//! resource is inserted, and the stage keeps the old state (without running
+//! its exit systems yet) until the duration has elapsed, or until a system
+//! calls [`Transitioning::finish`]. Then, the exit/enter stages run as usual.
+//!
+//! While a transition is in progress, the "transition systems" run once per
+//! frame (when `Time` has been updated since the last time they ran), and any
+//! new requests (like [`NextState`](super::NextState)) stay pending until it is
+//! complete. With [checkpoints](StateTransitionStage::checkpoint), only the first
+//! stage to run in each frame advances the transition.
+
+use bevy_ecs::schedule::{IntoSystemDescriptor, Stage, StateData, SystemSet};
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+use bevy_time::Time;
+use bevy_utils::{Duration, Instant};
+
+use super::{StateTransitionStage, StateTransitions};
+
+/// Resource that exists while a timed transition is in progress
+///
+/// See [`StateTransitionStage::set_transition_duration`].
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct Transitioning<T> {
+    /// The state we are transitioning from (still the `CurrentState`)
+    pub from: T,
+    /// The state we are transitioning to
+    pub to: T,
+    /// How far along the transition is, from `0.0` to `1.0`
+    pub progress: f32,
+    elapsed: Duration,
+    duration: Duration,
+    finished: bool,
+    going_back: bool,
+    /// The `Time` update that the transition was last advanced for
+    last_update: Option<Instant>,
+}
+
+impl<T> Transitioning<T> {
+    /// How much time has elapsed since the transition started
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The total duration of the transition
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Complete the transition now, without waiting for the duration to elapse
+    ///
+    /// The exit/enter stages will run the next time the `StateTransitionStage` runs.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+}
+
+impl<T: StateData> StateTransitionStage<T> {
+    /// Make transitions take the given amount of time
+    ///
+    /// The time is measured using `bevy_time::Time`. If there is no `Time`
+    /// resource, transitions only complete when [`Transitioning::finish`] is called.
+    pub fn set_transition_duration(&mut self, duration: Duration) {
+        self.transitions().transition_duration = Some(duration);
+    }
+
+    /// Builder version of `set_transition_duration`
+    pub fn with_transition_duration(mut self, duration: Duration) -> Self {
+        self.set_transition_duration(duration);
+        self
+    }
+
+    /// Add a system to run once per frame while a timed transition is in progress
+    pub fn add_transition_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) {
+        self.transitions().transition_stage.add_system(system);
+    }
+
+    /// Add a system set to run once per frame while a timed transition is in progress
+    pub fn add_transition_system_set(&mut self, system_set: SystemSet) {
+        self.transitions().transition_stage.add_system_set(system_set);
+    }
+
+    /// Builder version of `add_transition_system`
+    pub fn with_transition_system<Params>(mut self, system: impl IntoSystemDescriptor<Params>) -> Self {
+        self.add_transition_system(system);
+        self
+    }
+
+    /// Builder version of `add_transition_system_set`
+    pub fn with_transition_system_set(mut self, system_set: SystemSet) -> Self {
+        self.add_transition_system_set(system_set);
+        self
+    }
+}
+
+impl<T: StateData> StateTransitions<T> {
+    /// Begin a timed transition (if enabled)
+    ///
+    /// Returns `false` if transitions are not timed.
+    pub(super) fn start_timed_transition(&mut self, world: &mut World, from: &T, to: &T, going_back: bool) -> bool {
+        let duration = match self.transition_duration {
+            Some(duration) => duration,
+            None => return false,
+        };
+        world.insert_resource(Transitioning {
+            from: from.clone(),
+            to: to.clone(),
+            progress: 0.0,
+            elapsed: Duration::ZERO,
+            duration,
+            finished: false,
+            going_back,
+            last_update: last_time_update(world),
+        });
+        self.run_transition_stage(world);
+        true
+    }
+
+    /// Advance the timed transition in progress (if any), at most once per `Time` update
+    ///
+    /// If it is done, performs the transition. Returns `true` if it is still in progress.
+    pub(super) fn update_timed_transition(&mut self, world: &mut World) -> bool {
+        let last_update = last_time_update(world);
+        let delta = world.get_resource::<Time>()
+            .map(|time| time.delta())
+            .unwrap_or_default();
+        let (done, new_frame) = match world.get_resource_mut::<Transitioning<T>>() {
+            Some(mut transitioning) => {
+                // without `Time`, we cannot tell frames apart
+                let new_frame = last_update.is_none() || last_update != transitioning.last_update;
+                if new_frame {
+                    transitioning.last_update = last_update;
+                    transitioning.elapsed += delta;
+                    transitioning.progress = if transitioning.duration.is_zero() {
+                        1.0
+                    } else {
+                        (transitioning.elapsed.as_secs_f32() / transitioning.duration.as_secs_f32()).min(1.0)
+                    };
+                }
+                (transitioning.finished || transitioning.elapsed >= transitioning.duration, new_frame)
+            }
+            None => return false,
+        };
+        if done {
+            let transitioning = world.remove_resource::<Transitioning<T>>()
+                .expect("Transitioning resource should exist");
+            self.transition(world, &transitioning.from, &transitioning.to, transitioning.going_back);
+            false
+        } else {
+            if new_frame {
+                self.run_transition_stage(world);
+            }
+            true
+        }
+    }
+
+    /// Run the systems of a timed transition in progress
+    fn run_transition_stage(&mut self, world: &mut World) {
+        #[cfg(feature = "testing")]
+        if let Some(transitioning) = world.get_resource::<Transitioning<T>>() {
+            let record = crate::testing::StateRecord::Transitioning {
+                from: transitioning.from.clone(),
+                to: transitioning.to.clone(),
+            };
+            crate::testing::StateRecorder::record(world, record);
+        }
+        self.transition_stage.run(world);
+    }
+}
+
+/// When `Time` was last updated (if there is a `Time` resource)
+fn last_time_update(world: &World) -> Option<Instant> {
+    world.get_resource::<Time>().and_then(|time| time.last_update())
+}
@@ -0,0 +1,310 @@
+//! Utilities for testing state machines, without a full Bevy `App`
+//!
+//! [`TestHarness`] is a minimal setup: a `World`, a `Schedule` with a single
+//! [`TestStage::Update`] stage, and (with the `"timed"` cargo feature) a `Time`
+//! resource that advances by a fixed amount every frame (so timed transitions
+//! are deterministic).
+//!
+//! To check the order in which things happened, insert a [`StateRecorder`]
+//! resource (or use [`TestHarness::record_state`]). Every time an enter/exit/
+//! transition stage runs, an entry is added to it.
+
+use bevy_ecs::schedule::{IntoSystemDescriptor, Schedule, Stage, StageLabel, StateData, SystemStage};
+use bevy_ecs::system::Resource;
+use bevy_ecs::world::World;
+#[cfg(feature = "timed")]
+use bevy_time::Time;
+#[cfg(feature = "timed")]
+use bevy_utils::{Duration, Instant};
+
+use crate::state::schedule::ScheduleLooplessStateExt;
+use crate::state::{CurrentState, NextState, StateTransitionStage, StateTransitionStageLabel};
+
+/// Stage labels of the [`TestHarness`] schedule
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(StageLabel)]
+pub enum TestStage {
+    /// Where regular systems run; state transition stages are added before it
+    Update,
+}
+
+/// Minimal `World` + `Schedule` for running state machines in tests
+pub struct TestHarness {
+    /// The world
+    pub world: World,
+    /// The schedule, run once per frame
+    pub schedule: Schedule,
+    #[cfg(feature = "timed")]
+    frame_time: Duration,
+    #[cfg(feature = "timed")]
+    now: Instant,
+}
+
+impl Default for TestHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestHarness {
+    /// Create a new harness, with frames lasting 1/60 of a second
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut world = World::new();
+        #[cfg(feature = "timed")]
+        world.init_resource::<Time>();
+        let mut schedule = Schedule::default();
+        schedule.add_stage(TestStage::Update, SystemStage::parallel());
+        Self {
+            world,
+            schedule,
+            #[cfg(feature = "timed")]
+            frame_time: Duration::from_secs_f64(1.0 / 60.0),
+            #[cfg(feature = "timed")]
+            now: Instant::now(),
+        }
+    }
+
+    /// Set how much `Time` advances every frame
+    #[cfg(feature = "timed")]
+    pub fn set_frame_time(&mut self, frame_time: Duration) {
+        self.frame_time = frame_time;
+    }
+
+    /// Builder version of `set_frame_time`
+    #[cfg(feature = "timed")]
+    pub fn with_frame_time(mut self, frame_time: Duration) -> Self {
+        self.set_frame_time(frame_time);
+        self
+    }
+
+    /// Add a `StateTransitionStage`, before [`TestStage::Update`]
+    pub fn add_state<T: StateData>(&mut self, init: T) -> &mut Self {
+        self.schedule.add_loopless_state_before_stage(TestStage::Update, init);
+        self
+    }
+
+    /// Access the `StateTransitionStage` of a state type, to configure it
+    pub fn state_stage_mut<T: StateData>(&mut self) -> &mut StateTransitionStage<T> {
+        self.schedule.get_stage_mut::<StateTransitionStage<T>>(StateTransitionStageLabel::from_type::<T>())
+            .expect("State Transition Stage not found (assuming auto-added label)")
+    }
+
+    /// Add a system to [`TestStage::Update`]
+    pub fn add_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut Self {
+        self.schedule.add_system_to_stage(TestStage::Update, system);
+        self
+    }
+
+    /// Start recording the enter/exit/transition stages of a state type
+    ///
+    /// Inserts an empty [`StateRecorder`] resource.
+    pub fn record_state<T: StateData>(&mut self) -> &mut Self {
+        self.world.insert_resource(StateRecorder::<T>::default());
+        self
+    }
+
+    /// Request a transition, by inserting a `NextState`
+    ///
+    /// It is performed the next time the state transition stage runs.
+    pub fn request_transition<T: StateData>(&mut self, state: T) -> &mut Self {
+        self.world.insert_resource(NextState(state));
+        self
+    }
+
+    /// Run one frame: advance `Time` (if enabled) and run the schedule
+    ///
+    /// (`Time` reports a zero delta on the first frame)
+    pub fn step(&mut self) -> &mut Self {
+        #[cfg(feature = "timed")]
+        {
+            self.now += self.frame_time;
+            self.world.resource_mut::<Time>().update_with_instant(self.now);
+        }
+        self.schedule.run(&mut self.world);
+        self
+    }
+
+    /// Run the given number of frames
+    pub fn step_frames(&mut self, n: usize) -> &mut Self {
+        for _ in 0..n {
+            self.step();
+        }
+        self
+    }
+
+    /// The current value of a state type, if it exists
+    pub fn current_state<T: StateData>(&self) -> Option<&T> {
+        self.world.get_resource::<CurrentState<T>>().map(|current| &current.0)
+    }
+
+    /// Panic if not in the given state
+    pub fn assert_state<T: StateData>(&mut self, state: T) -> &mut Self {
+        assert_state(&self.world, state);
+        self
+    }
+
+    /// The entries recorded for a state type (see [`record_state`](Self::record_state))
+    pub fn recorded<T: StateData>(&self) -> &[StateRecord<T>] {
+        self.world.get_resource::<StateRecorder<T>>()
+            .map(|recorder| recorder.records())
+            .unwrap_or(&[])
+    }
+}
+
+/// Panic if the `World` is not in the given state
+pub fn assert_state<T: StateData>(world: &World, state: T) {
+    match world.get_resource::<CurrentState<T>>() {
+        Some(current) => assert_eq!(
+            current.0, state,
+            "Wrong state for state type {}", std::any::type_name::<T>(),
+        ),
+        None => panic!(
+            "Expected state {:?}, but state type {} does not exist",
+            state, std::any::type_name::<T>(),
+        ),
+    }
+}
+
+/// Something that happened in a `StateTransitionStage`, recorded by [`StateRecorder`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateRecord<T> {
+    /// The enter stages of a state ran
+    Enter(T),
+    /// The exit stages of a state ran
+    Exit(T),
+    /// The re-enter stage of a state ran (see `SameStatePolicy::Reenter`)
+    Reenter(T),
+    /// The transition systems of a timed transition ran (see the `timed` module)
+    Transitioning {
+        /// The state we are transitioning from
+        from: T,
+        /// The state we are transitioning to
+        to: T,
+    },
+}
+
+/// Resource with an ordered log of everything that happened in the `StateTransitionStage` of a state type
+///
+/// Recording happens only while this resource exists.
+#[derive(Debug, Clone)]
+#[derive(Resource)]
+pub struct StateRecorder<T> {
+    records: Vec<StateRecord<T>>,
+}
+
+impl<T> Default for StateRecorder<T> {
+    fn default() -> Self {
+        Self {
+            records: Vec::new(),
+        }
+    }
+}
+
+impl<T: StateData> StateRecorder<T> {
+    /// The recorded entries, oldest first
+    pub fn records(&self) -> &[StateRecord<T>] {
+        &self.records
+    }
+
+    /// Forget all recorded entries
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Add an entry, if the world has a recorder for this state type
+    pub(crate) fn record(world: &mut World, record: StateRecord<T>) {
+        if let Some(mut recorder) = world.get_resource_mut::<StateRecorder<T>>() {
+            recorder.records.push(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::SameStatePolicy;
+
+    use super::{StateRecord, StateRecorder, TestHarness};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum MyState {
+        A,
+        B,
+    }
+
+    #[test]
+    fn enter_exit_order() {
+        let mut harness = TestHarness::new();
+        harness.add_state(MyState::A).record_state::<MyState>();
+        harness.step()
+            .assert_state(MyState::A)
+            .request_transition(MyState::B)
+            .step()
+            .assert_state(MyState::B);
+        assert_eq!(harness.recorded::<MyState>(), [
+            StateRecord::Enter(MyState::A),
+            StateRecord::Exit(MyState::A),
+            StateRecord::Enter(MyState::B),
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature = "timed")]
+    fn first_frame_zero_delta() {
+        use bevy_time::Time;
+        use bevy_utils::Duration;
+
+        let frame_time = Duration::from_millis(100);
+        let mut harness = TestHarness::new().with_frame_time(frame_time);
+        harness.step();
+        assert_eq!(harness.world.resource::<Time>().delta(), Duration::ZERO);
+        harness.step();
+        assert_eq!(harness.world.resource::<Time>().delta(), frame_time);
+    }
+
+    #[test]
+    fn recorder() {
+        let mut harness = TestHarness::new();
+        harness.add_state(MyState::A);
+        harness.state_stage_mut::<MyState>().set_same_state_policy(SameStatePolicy::Reenter);
+        // nothing is recorded without a recorder
+        harness.step();
+        assert!(harness.recorded::<MyState>().is_empty());
+
+        harness.record_state::<MyState>()
+            .request_transition(MyState::A)
+            .step()
+            .request_transition(MyState::B)
+            .step()
+            .assert_state(MyState::B);
+        assert_eq!(harness.recorded::<MyState>(), [
+            StateRecord::Reenter(MyState::A),
+            StateRecord::Exit(MyState::A),
+            StateRecord::Enter(MyState::B),
+        ]);
+
+        harness.world.resource_mut::<StateRecorder<MyState>>().clear();
+        assert!(harness.recorded::<MyState>().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "timed")]
+    fn recorder_timed_transition() {
+        use bevy_utils::Duration;
+
+        let mut harness = TestHarness::new().with_frame_time(Duration::from_millis(100));
+        harness.add_state(MyState::A).record_state::<MyState>();
+        harness.state_stage_mut::<MyState>().set_transition_duration(Duration::from_millis(150));
+        harness.step()
+            .request_transition(MyState::B)
+            .step_frames(3)
+            .assert_state(MyState::B);
+        assert_eq!(harness.recorded::<MyState>(), [
+            StateRecord::Enter(MyState::A),
+            StateRecord::Transitioning { from: MyState::A, to: MyState::B },
+            StateRecord::Transitioning { from: MyState::A, to: MyState::B },
+            StateRecord::Exit(MyState::A),
+            StateRecord::Enter(MyState::B),
+        ]);
+    }
+}